@@ -1,65 +1,63 @@
 use std::{slice::Iter, str::FromStr};
 
-use crate::ParseToken;
+use crate::{document::Block, ParseToken, Span, Token};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CodeKind {
-    Cpp(usize),
-    CStandard(usize),
-    CSharp(usize),
-    Go(usize),
-    Haskell(usize),
-    Java(usize),
-    JavaScript(usize),
-    Lua(usize),
-    Python(usize),
-    Ruby(usize),
-    Rust(usize),
-    None(usize),
-    Unknown(Box<str>, usize),
+    Cpp,
+    CStandard,
+    CSharp,
+    Go,
+    Haskell,
+    Java,
+    JavaScript,
+    Lua,
+    Python,
+    Ruby,
+    Rust,
+    None,
+    Unknown(Box<str>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// How code is set apart from the text around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Fence {
+    /// A run of backticks on each side. Runs shorter than three make a code
+    /// span, longer ones fence a block on lines of their own.
+    Backticks(usize),
+    /// A run of three or more tildes on the lines before and after a block.
+    Tildes(usize),
+    /// Four spaces of indentation on every line of a block, which leaves no
+    /// room for a kind.
+    Indent,
+}
+
+#[derive(Debug, Clone)]
 pub struct Code {
-    pub content: Box<str>,
-    pub kind: CodeKind,
-}
-
-impl CodeKind {
-    pub fn increase_indent(mut self, n: usize) -> Self {
-        self = match self {
-            CodeKind::Cpp(c) => CodeKind::Cpp(c + n),
-            CodeKind::CSharp(c) => CodeKind::CSharp(c + n),
-            CodeKind::CStandard(c) => CodeKind::CStandard(c + n),
-            CodeKind::Go(c) => CodeKind::Go(c + n),
-            CodeKind::Haskell(c) => CodeKind::Haskell(c + n),
-            CodeKind::Java(c) => CodeKind::Java(c + n),
-            CodeKind::JavaScript(c) => CodeKind::JavaScript(c + n),
-            CodeKind::Lua(c) => CodeKind::Lua(c + n),
-            CodeKind::Python(c) => CodeKind::Python(c + n),
-            CodeKind::Ruby(c) => CodeKind::Ruby(c + n),
-            CodeKind::Rust(c) => CodeKind::Rust(c + n),
-            CodeKind::None(c) => CodeKind::None(c + n),
-            CodeKind::Unknown(s, c) => CodeKind::Unknown(s, c + n),
-        };
-        self
+    pub(crate) content: Box<str>,
+    pub(crate) kind: CodeKind,
+    pub(crate) fence: Fence,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Code {
+    content,
+    kind,
+    fence
+});
+
+impl Code {
+    pub fn content(&self) -> &str {
+        &self.content
     }
-    pub fn get_index(&self) -> usize {
-        match self {
-            &Self::Cpp(c) => c,
-            &Self::CSharp(c) => c,
-            &Self::CStandard(c) => c,
-            &Self::Go(c) => c,
-            &Self::Haskell(c) => c,
-            &Self::Java(c) => c,
-            &Self::JavaScript(c) => c,
-            &Self::Lua(c) => c,
-            &Self::Python(c) => c,
-            &Self::Ruby(c) => c,
-            &Self::Rust(c) => c,
-            &Self::None(c) => c,
-            &Self::Unknown(_, c) => c,
-        }
+    pub fn kind(&self) -> &CodeKind {
+        &self.kind
+    }
+    pub fn fence(&self) -> Fence {
+        self.fence
+    }
+    pub fn span(&self) -> Span {
+        self.span
     }
 }
 
@@ -67,60 +65,64 @@ impl FromStr for CodeKind {
     type Err = crate::ParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "" => Ok(CodeKind::None(0)),
-            "cpp" => Ok(CodeKind::Cpp(0)),
-            "csharp" => Ok(CodeKind::CSharp(0)),
-            "c" => Ok(CodeKind::CStandard(0)),
-            "go" => Ok(CodeKind::Go(0)),
-            "haskell" => Ok(CodeKind::Haskell(0)),
-            "java" => Ok(CodeKind::Java(0)),
-            "javascript" => Ok(CodeKind::JavaScript(0)),
-            "lua" => Ok(CodeKind::Lua(0)),
-            "python" => Ok(CodeKind::Python(0)),
-            "ruby" => Ok(CodeKind::Ruby(0)),
-            "rust" => Ok(CodeKind::Rust(0)),
-            _ => Ok(CodeKind::Unknown(s.to_string().into_boxed_str(), 0)),
+            "" => Ok(CodeKind::None),
+            "cpp" => Ok(CodeKind::Cpp),
+            "csharp" => Ok(CodeKind::CSharp),
+            "c" => Ok(CodeKind::CStandard),
+            "go" => Ok(CodeKind::Go),
+            "haskell" => Ok(CodeKind::Haskell),
+            "java" => Ok(CodeKind::Java),
+            "javascript" => Ok(CodeKind::JavaScript),
+            "lua" => Ok(CodeKind::Lua),
+            "python" => Ok(CodeKind::Python),
+            "ruby" => Ok(CodeKind::Ruby),
+            "rust" => Ok(CodeKind::Rust),
+            _ => Ok(CodeKind::Unknown(s.to_string().into_boxed_str())),
         }
     }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for CodeKind {
     fn to_string(&self) -> String {
         match self {
-            CodeKind::Cpp(_) => String::from("cpp"),
-            CodeKind::CSharp(_) => String::from("csharp"),
-            CodeKind::CStandard(_) => String::from("c"),
-            CodeKind::Go(_) => String::from("go"),
-            CodeKind::Haskell(_) => String::from("haskell"),
-            CodeKind::Java(_) => String::from("java"),
-            CodeKind::JavaScript(_) => String::from("javascript"),
-            CodeKind::Lua(_) => String::from("lua"),
-            CodeKind::Python(_) => String::from("python"),
-            CodeKind::Ruby(_) => String::from("ruby"),
-            CodeKind::Rust(_) => String::from("rust"),
-            CodeKind::None(_) => String::new(),
-            CodeKind::Unknown(s, _) => s.to_string(),
+            CodeKind::Cpp => String::from("cpp"),
+            CodeKind::CSharp => String::from("csharp"),
+            CodeKind::CStandard => String::from("c"),
+            CodeKind::Go => String::from("go"),
+            CodeKind::Haskell => String::from("haskell"),
+            CodeKind::Java => String::from("java"),
+            CodeKind::JavaScript => String::from("javascript"),
+            CodeKind::Lua => String::from("lua"),
+            CodeKind::Python => String::from("python"),
+            CodeKind::Ruby => String::from("ruby"),
+            CodeKind::Rust => String::from("rust"),
+            CodeKind::None => String::new(),
+            CodeKind::Unknown(s) => s.to_string(),
         }
     }
 }
 
+/// Parses the rest of a code span opened by `n` backticks. A line break in it
+/// reads as a space, but a blank line leaves it unclosed.
 #[inline]
 fn parse_inner(
-    iter: &mut core::slice::Iter<'_, ParseToken>,
+    iter: &mut core::slice::Iter<'_, Token>,
     n: usize,
 ) -> Result<Code, crate::ParseError> {
     let mut content = String::new();
     let mut count = 0;
-    while let Some(tok) = iter.next() {
-        match tok {
+    for tok in iter.by_ref() {
+        match &tok.kind {
+            ParseToken::RepeatSpecial('\n', 1) => content.push(' '),
             ParseToken::RepeatSpecial('\n', _) => {
-                return Err(crate::ParseError::UnexpectedChar('\n'))
+                return Err(crate::ParseErrorKind::UnexpectedChar('\n').into())
             }
             ParseToken::RepeatSpecial('`', c) => {
                 if *c < n {
                     content.push('`')
                 } else if content.is_empty() {
-                    return Err(crate::ParseError::EmptyContent);
+                    return Err(crate::ParseErrorKind::EmptyContent.into());
                 } else {
                     count = n;
                     break;
@@ -131,94 +133,168 @@ fn parse_inner(
             ParseToken::Number(_, _) => content.push_str(&tok.to_string()),
         }
     }
-    return if count != 0 {
+    if count != 0 {
         Ok(Code {
             content: content.into_boxed_str(),
-            kind: CodeKind::None(count),
+            kind: CodeKind::None,
+            fence: Fence::Backticks(count),
+            span: Span::default(),
         })
     } else {
-        Err(crate::ParseError::UnexpectedEnd)
-    };
+        Err(crate::ParseErrorKind::UnexpectedEnd.into())
+    }
 }
 
+/// Whether the end of `content` is the start of a line, ignoring up to three
+/// spaces of indentation, so that a closing fence may follow.
+#[inline]
+fn fence_line_start(content: &str) -> bool {
+    let trimmed = content.trim_end_matches(' ');
+    trimmed.ends_with('\n') && content.len() - trimmed.len() <= 3
+}
+
+/// Whether the tokens up to the next line break are all whitespace.
+#[inline]
+fn rest_of_line_blank(tokens: &[Token]) -> bool {
+    let end = tokens
+        .iter()
+        .position(|t| matches!(t.kind, ParseToken::RepeatSpecial('\n', _)))
+        .unwrap_or(tokens.len());
+    crate::is_blank(&tokens[..end])
+}
+
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Code {
     fn to_string(&self) -> String {
-        let prefix = "`".repeat(self.kind.get_index());
-        format!(
-            "{}{}{}{}",
-            prefix,
-            self.kind.to_string(),
-            self.content,
-            prefix
-        )
+        let prefix = match self.fence {
+            Fence::Backticks(n) if n < 3 => {
+                let prefix = "`".repeat(n);
+                return format!("{}{}{}", prefix, self.content, prefix);
+            }
+            Fence::Backticks(n) => "`".repeat(n),
+            Fence::Tildes(n) => "~".repeat(n),
+            Fence::Indent => {
+                return self
+                    .content
+                    .split('\n')
+                    .map(|l| match l {
+                        "" => String::new(),
+                        l => format!("    {}", l),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+        // An info string starting with a tilde would lengthen a tilde fence.
+        let info = match self.kind.to_string() {
+            s if s.starts_with('~') => format!(" {}", s),
+            s => s,
+        };
+        if self.content.is_empty() {
+            return format!("{}{}\n{}", prefix, info, prefix);
+        }
+        format!("{}{}\n{}\n{}", prefix, info, self.content, prefix)
     }
 }
 
 impl super::Element for Code {
-    fn parse(iter: &mut Iter<ParseToken>) -> Result<Self, crate::ParseError> {
-        if let Some(first_tok) = iter.next() {
-            return match first_tok {
-                ParseToken::RepeatSpecial('`', 1) => parse_inner(iter, 1),
-                ParseToken::RepeatSpecial('`', 2) => parse_inner(iter, 2),
-                ParseToken::RepeatSpecial('`', n) => {
-                    let mut kind: String = String::new();
-                    while let Some(tok) = iter.next() {
-                        match tok {
-                            ParseToken::RepeatSpecial('\n', _) => {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        let mut code = parse_code(iter)?;
+        code.span = crate::consumed(before, iter);
+        Ok(code)
+    }
+}
+
+fn parse_code(iter: &mut Iter<Token>) -> Result<Code, crate::ParseError> {
+    if let Some(ParseToken::RepeatSpecial(' ', n)) = crate::kind(iter.as_slice(), 0) {
+        if *n >= 4 {
+            return Ok(parse_indented(iter));
+        }
+    }
+    if let Some(first_tok) = iter.next() {
+        return match &first_tok.kind {
+            ParseToken::RepeatSpecial('`', 1) => parse_inner(iter, 1),
+            ParseToken::RepeatSpecial('`', 2) => parse_inner(iter, 2),
+            ParseToken::RepeatSpecial(c @ ('`' | '~'), n) if *n >= 3 => {
+                let (kind, _) = crate::take_line(iter);
+                let mut content = String::new();
+                while let Some(tok) = iter.next() {
+                    match &tok.kind {
+                        ParseToken::RepeatSpecial(d, m) if d == c => {
+                            if n <= m
+                                && (content.is_empty() || fence_line_start(&content))
+                                && rest_of_line_blank(iter.as_slice())
+                            {
                                 break;
                             }
-                            ParseToken::String(s) => kind.push_str(s),
-                            ParseToken::RepeatSpecial(c, _) => {
-                                return Err(crate::ParseError::UnexpectedChar(*c))
-                            }
-                            ParseToken::Number(_, _) => {
-                                return Err(crate::ParseError::UnexpectedString(tok.to_string()))
-                            }
+                            content.push_str(&d.to_string().repeat(*m));
                         }
-                    }
-                    let mut content = String::new();
-                    while let Some(tok) = iter.next() {
-                        match tok {
-                            ParseToken::RepeatSpecial('\n', _) => continue,
-                            ParseToken::RepeatSpecial('`', m) => {
-                                if n <= m {
-                                    break;
-                                }
-                                content.push_str(&"`".repeat(*m));
-                            }
-                            ParseToken::RepeatSpecial(c, n) => {
-                                content.push_str(&c.to_string().repeat(*n))
-                            }
-                            ParseToken::String(s) => content.push_str(s),
-                            ParseToken::Number(_, _) => {
-                                content.push_str(&tok.to_string());
-                            }
+                        ParseToken::RepeatSpecial(c, n) => {
+                            content.push_str(&c.to_string().repeat(*n))
+                        }
+                        ParseToken::String(s) => content.push_str(s),
+                        ParseToken::Number(_, _) => {
+                            content.push_str(&tok.to_string());
                         }
                     }
-                    Ok(Code {
-                        content: content.into_boxed_str(),
-                        kind: CodeKind::from_str(&kind).unwrap().increase_indent(*n),
-                    })
-                }
-                ParseToken::String(s) => {
-                    return Err(crate::ParseError::UnexpectedString(s.to_owned()))
                 }
-                ParseToken::RepeatSpecial(c, _) => {
-                    return Err(crate::ParseError::UnexpectedChar(*c))
-                }
-                ParseToken::Number(_, _) => {
-                    return Err(crate::ParseError::UnexpectedString(first_tok.to_string()))
+                if let Some(i) = content.rfind('\n') {
+                    if content[i + 1..].trim_start_matches(' ').is_empty() {
+                        content.truncate(i);
+                    }
                 }
-            };
-        }
-        return Err(crate::ParseError::EmptyDocument);
+                let fence = match c {
+                    '`' => Fence::Backticks(*n),
+                    _ => Fence::Tildes(*n),
+                };
+                Ok(Code {
+                    content: content.into_boxed_str(),
+                    kind: CodeKind::from_str(kind.trim()).unwrap(),
+                    fence,
+                    span: Span::default(),
+                })
+            }
+            ParseToken::String(s) => {
+                return Err(crate::ParseErrorKind::UnexpectedString(s.to_owned()).into())
+            }
+            ParseToken::RepeatSpecial(c, _) => {
+                return Err(crate::ParseErrorKind::UnexpectedChar(*c).into())
+            }
+            ParseToken::Number(_, _) => {
+                return Err(crate::ParseErrorKind::UnexpectedString(first_tok.to_string()).into())
+            }
+        };
     }
+    Err(crate::ParseErrorKind::EmptyDocument.into())
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Parses the rest of `iter` as a block of code indented by four spaces,
+/// leaving out the blank lines after it.
+fn parse_indented(iter: &mut Iter<Token>) -> Code {
+    let content: String = crate::dedent(iter.as_slice(), 4)
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+    iter.by_ref().for_each(drop);
+    let last = content.trim_end().len();
+    let end = content[last..]
+        .find('\n')
+        .map_or(content.len(), |i| last + i);
+    let content = content[..end].trim_end_matches('\r');
+    Code {
+        content: content.into(),
+        kind: CodeKind::None,
+        fence: Fence::Indent,
+        span: Span::default(),
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CodeBuilder {
     content: String,
     kind: Option<CodeKind>,
+    fence: Option<Fence>,
 }
 
 impl CodeBuilder {
@@ -230,6 +306,11 @@ impl CodeBuilder {
         self.kind = Some(k);
         self
     }
+    /// Sets how the code is set apart, three backticks unless set.
+    pub fn fence(mut self, f: Fence) -> Self {
+        self.fence = Some(f);
+        self
+    }
 }
 
 impl crate::Builder for CodeBuilder {
@@ -241,20 +322,687 @@ impl crate::Builder for CodeBuilder {
         } else {
             return Err(crate::Error::IncompleteData);
         };
+        let fence = match self.fence.unwrap_or(Fence::Backticks(3)) {
+            Fence::Tildes(n) if n < 3 => return Err(crate::Error::InvalidData),
+            Fence::Backticks(0) => return Err(crate::Error::InvalidData),
+            Fence::Indent if kind != CodeKind::None => return Err(crate::Error::InvalidData),
+            f => f,
+        };
         Ok(Code {
             content: self.content.into_boxed_str(),
             kind,
+            fence,
+            span: Span::default(),
+        })
+    }
+}
+
+crate::impl_from_str!(Code);
+
+/// The start condition an HTML block was recognised by, which also decides
+/// where it ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HtmlKind {
+    /// A `pre`, `script`, `style` or `textarea` element, ending at its
+    /// closing tag.
+    Raw,
+    Comment,
+    ProcessingInstruction,
+    Declaration,
+    Cdata,
+    /// A known block-level tag such as `<div>`, ending at a blank line.
+    Block,
+    /// Any other complete tag alone on its line, ending at a blank line. It
+    /// cannot interrupt a paragraph.
+    Tag,
+}
+
+/// A block of raw HTML, kept exactly as it was written.
+#[derive(Debug, Clone)]
+pub struct Html {
+    pub(crate) content: Box<str>,
+    pub(crate) kind: HtmlKind,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Html { content, kind });
+
+impl Html {
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+    pub fn kind(&self) -> HtmlKind {
+        self.kind
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    /// The tag the block starts with, if it starts with one.
+    pub fn tag(&self) -> Option<crate::html::Tag> {
+        crate::html::Tag::parse(self.content.trim_start())
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Html {
+    fn to_string(&self) -> String {
+        self.content.to_string()
+    }
+}
+
+impl super::Element for Html {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        let first = before
+            .iter()
+            .find(|t| !matches!(t.kind, ParseToken::RepeatSpecial(' ', _)));
+        let content: String = iter.by_ref().map(|t| t.to_string()).collect();
+        let kind = crate::html::block_start(first_line(&content))
+            .ok_or_else(|| crate::unexpected_or_end(first))?;
+        Ok(Html {
+            content: content.trim_end_matches(['\r', '\n']).into(),
+            kind,
+            span: crate::span_of(before),
+        })
+    }
+}
+
+/// The first line of the HTML block `content`, which alone decides its kind.
+fn first_line(content: &str) -> &str {
+    let line = content.split('\n').next().unwrap_or_default();
+    line.trim_start_matches(' ').trim_end_matches('\r')
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HtmlBuilder {
+    content: String,
+}
+
+impl HtmlBuilder {
+    pub fn content(mut self, c: &str) -> Self {
+        self.content = c.to_string();
+        self
+    }
+}
+
+impl crate::Builder for HtmlBuilder {
+    type Output = Html;
+
+    fn build(self) -> Result<Self::Output, crate::Error> {
+        let kind = match crate::html::block_start(first_line(&self.content)) {
+            Some(k) => k,
+            None => return Err(crate::Error::InvalidData),
+        };
+        Ok(Html {
+            content: self.content.into_boxed_str(),
+            kind,
+            span: Span::default(),
+        })
+    }
+}
+
+crate::impl_from_str!(Html);
+
+/// A block quote, holding blocks of any kind, other quotes included.
+#[derive(Debug, Default, Clone)]
+pub struct Quote {
+    pub(crate) blocks: Box<[Block]>,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Quote { blocks });
+
+impl Quote {
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+    pub fn blocks_mut(&mut self) -> &mut [Block] {
+        &mut self.blocks
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Quote {
+    fn to_string(&self) -> String {
+        prefix_lines(&render_blocks(&self.blocks), ">", " ")
+    }
+}
+
+#[inline]
+fn render_blocks(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(|b| b.to_string())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Puts `prefix` and then `pad` before every line of `s`, leaving the pad out
+/// on empty lines.
+fn prefix_lines(s: &str, prefix: &str, pad: &str) -> String {
+    s.split('\n')
+        .map(|l| {
+            if l.is_empty() {
+                prefix.to_string()
+            } else {
+                format!("{}{}{}", prefix, pad, l)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl super::Element for Quote {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
+    }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let tokens = iter.as_slice();
+        let blocks = cx.nested(|cx| crate::document::parse_blocks(&unquote(tokens), cx))?;
+        iter.by_ref().for_each(drop);
+        Ok(Quote {
+            blocks: blocks.into_boxed_slice(),
+            span: crate::span_of(tokens),
+        })
+    }
+}
+
+/// Copies `tokens`, taking the `>` marker off the start of every line that
+/// has one, along with up to three spaces before it and a space after it.
+/// Lines without a marker continue a paragraph lazily and are kept as they
+/// are. The tokens that are left keep their place in the source.
+fn unquote(tokens: &[Token]) -> Vec<Token> {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let line_start = i == 0 || matches!(tokens[i - 1].kind, ParseToken::RepeatSpecial('\n', _));
+        let (n, rest) = crate::indent(&tokens[i..]);
+        if !line_start
+            || n > 3
+            || !matches!(
+                crate::kind(rest, 0),
+                Some(ParseToken::RepeatSpecial('>', _))
+            )
+        {
+            out.push(tokens[i].clone());
+            i += 1;
+            continue;
+        }
+        i += usize::from(n > 0);
+        let mut marker = tokens[i].clone();
+        i += 1;
+        if let Some(rest) = crate::text::split_at(&mut marker, 1) {
+            out.push(rest);
+            continue;
+        }
+        if let Some(ParseToken::RepeatSpecial(' ', _)) = crate::kind(tokens, i) {
+            let mut space = tokens[i].clone();
+            i += 1;
+            out.extend(crate::text::split_at(&mut space, 1));
+        }
+    }
+    out
+}
+
+/// Follows the lines of a quote to tell whether the innermost block left open
+/// is a paragraph, the only block a line without a `>` marker may lazily
+/// continue.
+#[derive(Debug, Default)]
+pub(crate) struct Lazy {
+    fence: Option<Fence>,
+    paragraph: bool,
+}
+
+impl Lazy {
+    /// Takes the quote on past `line`.
+    pub(crate) fn push(&mut self, line: &[Token]) {
+        let mut line = line.to_vec();
+        while matches!(crate::indent(&line), (0..=3, rest) if matches!(crate::kind(rest, 0), Some(ParseToken::RepeatSpecial('>', _))))
+        {
+            line = unquote(&line);
+        }
+        if let Some(fence) = self.fence {
+            if crate::document::is_closing_fence(&line, fence) {
+                self.fence = None;
+            }
+            return;
+        }
+        self.paragraph = match crate::document::block_start(&line) {
+            Some(crate::document::Start::Fence(fence)) => {
+                self.fence = Some(fence);
+                false
+            }
+            None if crate::is_blank(&line) => false,
+            None => self.paragraph || crate::indent(&line).0 < 4,
+            Some(crate::document::Start::List) => true,
+            Some(_) => false,
+        };
+    }
+    /// Whether a line without a marker continues the quote.
+    pub(crate) fn continues(&self) -> bool {
+        self.paragraph
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct QuoteBuilder {
+    blocks: Vec<Block>,
+}
+
+impl QuoteBuilder {
+    pub fn push(mut self, block: Block) -> Self {
+        self.blocks.push(block);
+        self
+    }
+}
+
+impl crate::Builder for QuoteBuilder {
+    type Output = Quote;
+
+    fn build(self) -> Result<Self::Output, crate::Error> {
+        Ok(Quote {
+            blocks: self.blocks.into_boxed_slice(),
+            span: Span::default(),
+        })
+    }
+}
+
+crate::impl_from_str!(Quote);
+
+/// The kind of an admonition, which decides how it is displayed.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AdmonitionKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+    /// Any other kind, as it was written.
+    Custom(Box<str>),
+}
+
+impl FromStr for AdmonitionKind {
+    type Err = crate::ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "note" => Ok(AdmonitionKind::Note),
+            "tip" => Ok(AdmonitionKind::Tip),
+            "important" => Ok(AdmonitionKind::Important),
+            "warning" => Ok(AdmonitionKind::Warning),
+            "caution" => Ok(AdmonitionKind::Caution),
+            _ => Ok(AdmonitionKind::Custom(s.into())),
+        }
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for AdmonitionKind {
+    fn to_string(&self) -> String {
+        match self {
+            AdmonitionKind::Note => String::from("note"),
+            AdmonitionKind::Tip => String::from("tip"),
+            AdmonitionKind::Important => String::from("important"),
+            AdmonitionKind::Warning => String::from("warning"),
+            AdmonitionKind::Caution => String::from("caution"),
+            AdmonitionKind::Custom(s) => s.to_string(),
+        }
+    }
+}
+
+/// The syntax an admonition is written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AdmonitionStyle {
+    /// A GitHub alert, a block quote starting with `[!NOTE]`.
+    #[default]
+    Alert,
+    /// An MkDocs admonition, `!!! note "Title"` followed by indented content.
+    MkDocs,
+}
+
+/// A note, tip, warning or other callout set apart from the text around it.
+#[derive(Debug, Clone)]
+pub struct Admonition {
+    pub(crate) kind: AdmonitionKind,
+    pub(crate) title: Option<Box<str>>,
+    pub(crate) blocks: Box<[Block]>,
+    pub(crate) style: AdmonitionStyle,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Admonition {
+    kind,
+    title,
+    blocks,
+    style
+});
+
+impl Admonition {
+    pub fn kind(&self) -> &AdmonitionKind {
+        &self.kind
+    }
+    /// The title shown instead of the name of the kind, if there is one.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+    pub fn blocks_mut(&mut self) -> &mut [Block] {
+        &mut self.blocks
+    }
+    /// The syntax the admonition was written in, which it is rendered in.
+    pub fn style(&self) -> AdmonitionStyle {
+        self.style
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    /// Renders the admonition in `style`, whichever syntax it was written in.
+    pub fn to_string_with(&self, style: AdmonitionStyle) -> String {
+        let inner = render_blocks(&self.blocks);
+        let kind = match &self.kind {
+            AdmonitionKind::Custom(s) => s.to_string(),
+            k if style == AdmonitionStyle::Alert => k.to_string().to_uppercase(),
+            k => k.to_string(),
+        };
+        let header = match (style, &self.title) {
+            (AdmonitionStyle::Alert, Some(t)) => format!("> [!{}] {}", kind, t),
+            (AdmonitionStyle::Alert, None) => format!("> [!{}]", kind),
+            (AdmonitionStyle::MkDocs, Some(t)) => format!("!!! {} \"{}\"", kind, t),
+            (AdmonitionStyle::MkDocs, None) => format!("!!! {}", kind),
+        };
+        if inner.is_empty() {
+            return header;
+        }
+        let body = match style {
+            AdmonitionStyle::Alert => prefix_lines(&inner, ">", " "),
+            AdmonitionStyle::MkDocs => prefix_lines(&inner, "", "    "),
+        };
+        format!("{}\n{}", header, body)
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Admonition {
+    fn to_string(&self) -> String {
+        self.to_string_with(self.style)
+    }
+}
+
+impl super::Element for Admonition {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
+    }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let tokens = iter.as_slice();
+        let (n, rest) = crate::indent(tokens);
+        let (style, lines) = match crate::kind(rest, 0) {
+            Some(ParseToken::RepeatSpecial('>', _)) if n <= 3 => {
+                (AdmonitionStyle::Alert, unquote(tokens))
+            }
+            Some(ParseToken::RepeatSpecial('!', 3)) if n <= 3 => {
+                (AdmonitionStyle::MkDocs, tokens.to_vec())
+            }
+            _ => return Err(crate::unexpected_or_end(rest.first())),
+        };
+        let end = lines
+            .iter()
+            .position(|t| matches!(t.kind, ParseToken::RepeatSpecial('\n', _)))
+            .unwrap_or(lines.len());
+        let header: String = lines[..end].iter().map(|t| t.to_string()).collect();
+        let header = match style {
+            AdmonitionStyle::Alert => alert_marker(header.trim()),
+            AdmonitionStyle::MkDocs => mkdocs_header(header.trim()),
+        };
+        let (kind, title) = header.ok_or_else(|| crate::unexpected(&rest[0]))?;
+        let body = &lines[(end + 1).min(lines.len())..];
+        let blocks = cx.nested(|cx| match style {
+            AdmonitionStyle::Alert => crate::document::parse_blocks(body, cx),
+            AdmonitionStyle::MkDocs => crate::document::parse_blocks(&crate::dedent(body, 4), cx),
+        })?;
+        iter.by_ref().for_each(drop);
+        Ok(Admonition {
+            kind,
+            title,
+            blocks: blocks.into_boxed_slice(),
+            style,
+            span: crate::span_of(tokens),
         })
     }
 }
 
-impl Default for CodeBuilder {
+/// Reads the `[!KIND] Title` line starting a GitHub alert.
+pub(crate) fn alert_marker(line: &str) -> Option<(AdmonitionKind, Option<Box<str>>)> {
+    let (kind, title) = line.strip_prefix("[!")?.split_once(']')?;
+    if !title.is_empty() && !title.starts_with(' ') {
+        return None;
+    }
+    let title = title.trim();
+    Some((
+        admonition_kind(kind)?,
+        (!title.is_empty()).then(|| title.into()),
+    ))
+}
+
+/// Reads the `!!! kind "Title"` line starting an MkDocs admonition.
+pub(crate) fn mkdocs_header(line: &str) -> Option<(AdmonitionKind, Option<Box<str>>)> {
+    let rest = line.strip_prefix("!!! ")?.trim();
+    let (kind, title) = rest
+        .split_once(' ')
+        .map_or((rest, ""), |(k, t)| (k, t.trim()));
+    let title = match title {
+        "" => None,
+        t => Some(t.strip_prefix('"')?.strip_suffix('"')?.into()),
+    };
+    Some((admonition_kind(kind)?, title))
+}
+
+#[inline]
+fn admonition_kind(s: &str) -> Option<AdmonitionKind> {
+    let valid = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_'));
+    valid.then(|| AdmonitionKind::from_str(s).unwrap())
+}
+
+/// Whether the quote starting with `line` is a GitHub alert.
+pub(crate) fn is_alert(line: &[Token]) -> bool {
+    let (_, rest) = crate::indent(line);
+    let text: String = match crate::kind(rest, 0) {
+        Some(ParseToken::RepeatSpecial('>', 1)) => {
+            rest[1..].iter().map(|t| t.to_string()).collect()
+        }
+        _ => return false,
+    };
+    alert_marker(text.trim()).is_some()
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AdmonitionBuilder {
+    kind: Option<AdmonitionKind>,
+    title: Option<Box<str>>,
+    blocks: Vec<Block>,
+    style: AdmonitionStyle,
+}
+
+impl AdmonitionBuilder {
+    pub fn kind(mut self, k: AdmonitionKind) -> Self {
+        self.kind = Some(k);
+        self
+    }
+    pub fn title(mut self, t: &str) -> Self {
+        self.title = Some(t.into());
+        self
+    }
+    pub fn style(mut self, s: AdmonitionStyle) -> Self {
+        self.style = s;
+        self
+    }
+    pub fn push(mut self, block: Block) -> Self {
+        self.blocks.push(block);
+        self
+    }
+}
+
+impl crate::Builder for AdmonitionBuilder {
+    type Output = Admonition;
+
+    fn build(self) -> Result<Self::Output, crate::Error> {
+        let kind = if let Some(k) = self.kind {
+            k
+        } else {
+            return Err(crate::Error::IncompleteData);
+        };
+        Ok(Admonition {
+            kind,
+            title: self.title,
+            blocks: self.blocks.into_boxed_slice(),
+            style: self.style,
+            span: Span::default(),
+        })
+    }
+}
+
+crate::impl_from_str!(Admonition);
+
+/// A thematic break, a line of three or more `-`, `*` or `_`.
+#[derive(Debug, Clone)]
+pub struct ThematicBreak {
+    pub(crate) marker: char,
+    pub(crate) count: usize,
+    pub(crate) spaced: bool,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(ThematicBreak {
+    marker,
+    count,
+    spaced
+});
+
+impl ThematicBreak {
+    /// The character the break is drawn with.
+    pub fn marker(&self) -> char {
+        self.marker
+    }
+    /// The number of marker characters in the break.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    /// Whether the markers are separated by spaces.
+    pub fn spaced(&self) -> bool {
+        self.spaced
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for ThematicBreak {
+    fn to_string(&self) -> String {
+        let sep = if self.spaced { " " } else { "" };
+        vec![self.marker.to_string(); self.count].join(sep)
+    }
+}
+
+impl super::Element for ThematicBreak {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        let tokens = iter.as_slice();
+        let line = crate::lines(tokens).first().map_or(tokens, |l| l.tokens);
+        let (marker, count) = thematic_break(line).ok_or_else(|| {
+            let (_, rest) = crate::indent(line);
+            crate::unexpected_or_end(rest.first())
+        })?;
+        iter.nth(line.len().max(1) - 1);
+        let (_, rest) = crate::indent(line);
+        let spaced = rest.windows(2).any(|w| {
+            matches!(w[0].kind, ParseToken::RepeatSpecial(' ' | '\t', _))
+                && matches!(w[1].kind, ParseToken::RepeatSpecial(c, _) if c == marker)
+        });
+        Ok(ThematicBreak {
+            marker,
+            count,
+            spaced,
+            span: crate::span_of(line),
+        })
+    }
+}
+
+/// The marker and number of markers of the thematic break `line` holds, if
+/// it is one. The markers may have spaces between them.
+pub(crate) fn thematic_break(line: &[Token]) -> Option<(char, usize)> {
+    let (n, rest) = crate::indent(line);
+    let marker = match crate::kind(rest, 0)? {
+        ParseToken::RepeatSpecial(c @ ('-' | '*' | '_'), _) if n <= 3 => *c,
+        _ => return None,
+    };
+    let mut len = 0;
+    for t in rest {
+        match t.kind {
+            ParseToken::RepeatSpecial(c, m) if c == marker => len += m,
+            ParseToken::RepeatSpecial(' ' | '\t', _) => {}
+            _ => return None,
+        }
+    }
+    (len >= 3).then_some((marker, len))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThematicBreakBuilder {
+    marker: char,
+    count: usize,
+    spaced: bool,
+}
+
+impl ThematicBreakBuilder {
+    pub fn marker(mut self, c: char) -> Self {
+        self.marker = c;
+        self
+    }
+    pub fn count(mut self, n: usize) -> Self {
+        self.count = n;
+        self
+    }
+    pub fn spaced(mut self, spaced: bool) -> Self {
+        self.spaced = spaced;
+        self
+    }
+}
+
+impl Default for ThematicBreakBuilder {
     fn default() -> Self {
         Self {
-            content: String::new(),
-            kind: None,
+            marker: '-',
+            count: 3,
+            spaced: false,
         }
     }
 }
 
-crate::impl_from_str!(Code);
+impl crate::Builder for ThematicBreakBuilder {
+    type Output = ThematicBreak;
+
+    fn build(self) -> Result<Self::Output, crate::Error> {
+        if !matches!(self.marker, '-' | '*' | '_') || self.count < 3 {
+            return Err(crate::Error::InvalidData);
+        }
+        Ok(ThematicBreak {
+            marker: self.marker,
+            count: self.count,
+            spaced: self.spaced,
+            span: Span::default(),
+        })
+    }
+}
+
+crate::impl_from_str!(ThematicBreak);
@@ -0,0 +1,634 @@
+use std::collections::HashSet;
+use std::io::Read;
+use std::slice::Iter;
+use std::str::FromStr;
+
+use crate::{block, indent, is_blank, lines, list, text, Element, ParseToken, Span, Token};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Block {
+    Heading(text::Heading),
+    Paragraph(text::Paragraph),
+    Code(block::Code),
+    List(list::Element),
+    Quote(block::Quote),
+    Reference(text::Reference),
+    Html(block::Html),
+    Admonition(block::Admonition),
+    ThematicBreak(block::ThematicBreak),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Document {
+    blocks: Box<[Block]>,
+}
+
+impl Document {
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+    pub fn iter(&self) -> Iter<'_, Block> {
+        self.blocks.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+    pub fn blocks_mut(&mut self) -> &mut [Block] {
+        &mut self.blocks
+    }
+    /// Every task list item in the document, nested ones included, in order.
+    pub fn tasks(&self) -> Vec<&list::Item> {
+        let mut tasks = Vec::new();
+        for block in all_blocks(&self.blocks) {
+            if let Block::List(l) = block {
+                tasks.extend(l.items().iter().filter(|i| i.is_task()));
+            }
+        }
+        tasks
+    }
+    /// The task list items that are not checked yet.
+    pub fn unchecked(&self) -> impl Iterator<Item = &list::Item> {
+        self.tasks()
+            .into_iter()
+            .filter(|i| i.checked() == Some(false))
+    }
+    /// The number of checked task items and the number of task items overall.
+    pub fn progress(&self) -> (usize, usize) {
+        let tasks = self.tasks();
+        let done = tasks.iter().filter(|i| i.checked() == Some(true)).count();
+        (done, tasks.len())
+    }
+    /// Every link and image in the document, nested ones included, in order.
+    pub fn links(&self) -> Vec<text::Link> {
+        let mut links = Vec::new();
+        for block in all_blocks(&self.blocks) {
+            match block {
+                Block::Paragraph(p) => text::collect_links(p.items(), &mut links),
+                Block::Heading(h) => text::collect_links(h.items(), &mut links),
+                Block::List(l) => {
+                    for item in l.items() {
+                        text::collect_links(item.items(), &mut links);
+                    }
+                }
+                _ => {}
+            }
+        }
+        links
+    }
+    /// The reference definition `label` refers to. Labels match regardless of
+    /// case and runs of whitespace, and the first matching definition wins.
+    pub fn reference(&self, label: &str) -> Option<&text::Reference> {
+        all_blocks(&self.blocks).into_iter().find_map(|b| match b {
+            Block::Reference(r) if r.matches(label) => Some(r),
+            _ => None,
+        })
+    }
+    /// The definition giving the destination and title of the reference link
+    /// `link`, or `None` if it links to no reference or to an undefined one.
+    pub fn resolve(&self, link: &text::Link) -> Option<&text::Reference> {
+        match link.src() {
+            text::LinkSource::Ref(label) => self.reference(label),
+            _ => None,
+        }
+    }
+    /// The reference links whose label matches no definition in the document.
+    pub fn dangling(&self) -> Vec<text::Link> {
+        self.links()
+            .into_iter()
+            .filter(|l| matches!(l.src(), text::LinkSource::Ref(_)) && self.resolve(l).is_none())
+            .collect()
+    }
+}
+
+/// The blocks in `blocks` along with the ones nested in quotes and
+/// admonitions, each followed by its content.
+fn all_blocks(blocks: &[Block]) -> Vec<&Block> {
+    let mut all = Vec::new();
+    for block in blocks {
+        all.push(block);
+        match block {
+            Block::Quote(q) => all.extend(all_blocks(q.blocks())),
+            Block::Admonition(a) => all.extend(all_blocks(a.blocks())),
+            Block::List(l) => {
+                for item in l.items() {
+                    all.extend(all_blocks(item.blocks()));
+                }
+            }
+            _ => {}
+        }
+    }
+    all
+}
+
+impl<'a> IntoIterator for &'a Document {
+    type Item = &'a Block;
+    type IntoIter = Iter<'a, Block>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.blocks.iter()
+    }
+}
+
+impl Element for Document {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
+    }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let blocks = parse_document(iter.as_slice(), cx)?;
+        iter.by_ref().for_each(drop);
+        Ok(Document {
+            blocks: blocks.into_boxed_slice(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Start {
+    Fence(block::Fence),
+    Heading,
+    Quote,
+    List,
+    Html(block::HtmlKind),
+    Admonition,
+    ThematicBreak,
+}
+
+pub(crate) fn block_start(line: &[Token]) -> Option<Start> {
+    let (n, rest) = indent(line);
+    if n > 3 {
+        return None;
+    }
+    match crate::kind(rest, 0)? {
+        ParseToken::RepeatSpecial('`', n) if *n >= 3 => {
+            let info = &rest[1..];
+            if info
+                .iter()
+                .any(|t| matches!(t.kind, ParseToken::RepeatSpecial('`', _)))
+            {
+                return None;
+            }
+            Some(Start::Fence(block::Fence::Backticks(*n)))
+        }
+        ParseToken::RepeatSpecial('~', n) if *n >= 3 => {
+            Some(Start::Fence(block::Fence::Tildes(*n)))
+        }
+        ParseToken::RepeatSpecial('#', 1..=6) => match crate::kind(rest, 1) {
+            None | Some(ParseToken::RepeatSpecial(' ', _)) => Some(Start::Heading),
+            _ => None,
+        },
+        ParseToken::RepeatSpecial('>', _) => Some(Start::Quote),
+        ParseToken::RepeatSpecial('<', 1) => {
+            let text: String = rest.iter().map(|t| t.to_string()).collect();
+            crate::html::block_start(&text).map(Start::Html)
+        }
+        ParseToken::RepeatSpecial('!', 3) => {
+            let text: String = rest.iter().map(|t| t.to_string()).collect();
+            block::mkdocs_header(text.trim_end()).map(|_| Start::Admonition)
+        }
+        _ if block::thematic_break(rest).is_some() => Some(Start::ThematicBreak),
+        _ if list::is_marker(rest) => Some(Start::List),
+        _ => None,
+    }
+}
+
+/// Whether `line` starts a block that may interrupt a paragraph. Lone HTML
+/// tags outside the known block-level ones may not, and neither may ordered
+/// lists starting at a number other than 1.
+pub(crate) fn interrupts_paragraph(line: &[Token]) -> bool {
+    match block_start(line) {
+        None | Some(Start::Html(block::HtmlKind::Tag)) => false,
+        Some(Start::List) => list::starts_at_one(line),
+        Some(_) => true,
+    }
+}
+
+pub(crate) fn is_closing_fence(line: &[Token], fence: block::Fence) -> bool {
+    let (c, len) = match fence {
+        block::Fence::Backticks(len) => ('`', len),
+        block::Fence::Tildes(len) => ('~', len),
+        block::Fence::Indent => return false,
+    };
+    let (n, rest) = indent(line);
+    match crate::kind(rest, 0) {
+        Some(ParseToken::RepeatSpecial(d, m)) if n <= 3 && *d == c && *m >= len => {
+            is_blank(&rest[1..])
+        }
+        _ => false,
+    }
+}
+
+/// The end of the lines following the one at `i` that are indented by four or
+/// more spaces, along with the blank lines between them but not after them.
+fn indented_end(lines: &[crate::Line], i: usize) -> usize {
+    let mut end = i + 1;
+    while end < lines.len() && (is_blank(lines[end].tokens) || indent(lines[end].tokens).0 >= 4) {
+        end += 1;
+    }
+    while is_blank(lines[end - 1].tokens) {
+        end -= 1;
+    }
+    end
+}
+
+/// Parses `tokens` as a `T` block. When parsing leniently, a block that fails
+/// to parse is recorded as a warning and kept as a paragraph of its literal
+/// text instead.
+fn parse_block<T: Element + Into<Block>>(
+    tokens: &[Token],
+    cx: &mut crate::Context,
+) -> Result<Block, crate::ParseError> {
+    match crate::parse_tokens_with::<T>(tokens, cx) {
+        Ok(block) => Ok(block.into()),
+        Err(e) if cx.lenient => {
+            cx.warnings.push(e);
+            Ok(literal(tokens, cx.extensions).into())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn literal(tokens: &[Token], extensions: text::Extensions) -> text::Paragraph {
+    let text: String = tokens.iter().map(|t| t.to_string()).collect();
+    let content = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let span = crate::span_of(tokens);
+    text::Paragraph {
+        items: Box::new([text::Item::Def(content.into_boxed_str(), span)]),
+        extensions,
+        span,
+    }
+}
+
+/// Parses `tokens` as the blocks of a whole document. Shortcut reference links
+/// only stand for links when their label is defined, so a document defining
+/// references is parsed a second time knowing their labels.
+fn parse_document(
+    tokens: &[Token],
+    cx: &mut crate::Context,
+) -> Result<Vec<Block>, crate::ParseError> {
+    let warnings = cx.warnings.len();
+    let blocks = parse_blocks(tokens, cx)?;
+    let labels: HashSet<String> = all_blocks(&blocks)
+        .into_iter()
+        .filter_map(|b| match b {
+            Block::Reference(r) => Some(text::label_key(r.name())),
+            _ => None,
+        })
+        .collect();
+    if labels.is_empty() {
+        return Ok(blocks);
+    }
+    cx.warnings.truncate(warnings);
+    cx.references = labels;
+    parse_blocks(tokens, cx)
+}
+
+pub(crate) fn parse_blocks(
+    tokens: &[Token],
+    cx: &mut crate::Context,
+) -> Result<Vec<Block>, crate::ParseError> {
+    let lines = lines(tokens);
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].tokens;
+        if is_blank(line) {
+            i += 1;
+            continue;
+        }
+        let (n, rest) = indent(line);
+        let start = lines[i].start + usize::from(n > 0);
+        if n >= 4 {
+            let end = indented_end(&lines, i);
+            let range = lines[i].start..lines[end - 1].end;
+            blocks.push(parse_block::<block::Code>(&tokens[range], cx)?);
+            i = end;
+            continue;
+        }
+        match block_start(line) {
+            Some(Start::Fence(fence)) => {
+                let close =
+                    (i + 1..lines.len()).find(|j| is_closing_fence(lines[*j].tokens, fence));
+                let end = close.map_or(tokens.len(), |j| lines[j].end);
+                let code = crate::dedent(&tokens[start..end], n);
+                blocks.push(parse_block::<block::Code>(&code, cx)?);
+                i = close.map_or(lines.len(), |j| j + 1);
+            }
+            Some(Start::Heading) => {
+                blocks.push(parse_block::<text::Heading>(rest, cx)?);
+                i += 1;
+            }
+            Some(Start::ThematicBreak) => {
+                blocks.push(parse_block::<block::ThematicBreak>(rest, cx)?);
+                i += 1;
+            }
+            Some(Start::Quote) => {
+                let mut end = i + 1;
+                let mut lazy = block::Lazy::default();
+                lazy.push(line);
+                while end < lines.len() {
+                    let next = lines[end].tokens;
+                    let continues = !is_blank(next) && !list::interrupts(next) && lazy.continues();
+                    if block_start(next) != Some(Start::Quote) && !continues {
+                        break;
+                    }
+                    lazy.push(next);
+                    end += 1;
+                }
+                let quote = &tokens[lines[i].start..lines[end - 1].end];
+                blocks.push(if block::is_alert(line) {
+                    parse_block::<block::Admonition>(quote, cx)?
+                } else {
+                    parse_block::<block::Quote>(quote, cx)?
+                });
+                i = end;
+            }
+            Some(Start::Admonition) => {
+                let end = indented_end(&lines, i);
+                let range = lines[i].start..lines[end - 1].end;
+                blocks.push(parse_block::<block::Admonition>(&tokens[range], cx)?);
+                i = end;
+            }
+            Some(Start::List) => {
+                let end = i + list::extent(&lines[i..]);
+                let range = lines[i].start..lines[end - 1].end;
+                blocks.push(parse_block::<list::Element>(&tokens[range], cx)?);
+                i = end;
+            }
+            Some(Start::Html(kind)) => {
+                let end = match kind {
+                    block::HtmlKind::Block | block::HtmlKind::Tag => (i + 1..lines.len())
+                        .find(|j| is_blank(lines[*j].tokens))
+                        .unwrap_or(lines.len()),
+                    _ => (i..lines.len())
+                        .find(|j| {
+                            let text: String =
+                                lines[*j].tokens.iter().map(|t| t.to_string()).collect();
+                            crate::html::block_end(kind, &text)
+                        })
+                        .map_or(lines.len(), |j| j + 1),
+                };
+                let range = lines[i].start..lines[end - 1].end;
+                blocks.push(parse_block::<block::Html>(&tokens[range], cx)?);
+                i = end;
+            }
+            None => {
+                if let Some(ParseToken::RepeatSpecial('[', 1)) = crate::kind(rest, 0) {
+                    if let Ok(r) = crate::parse_tokens::<text::Reference>(rest) {
+                        blocks.push(r.into());
+                        i += 1;
+                        continue;
+                    }
+                }
+                let mut end = i + 1;
+                let mut heading = false;
+                while end < lines.len() {
+                    let next = lines[end].tokens;
+                    if is_blank(next) {
+                        break;
+                    }
+                    if text::setext_underline(next).is_some() {
+                        heading = true;
+                        end += 1;
+                        break;
+                    }
+                    if interrupts_paragraph(next) {
+                        break;
+                    }
+                    end += 1;
+                }
+                let tokens = &tokens[start..lines[end - 1].end];
+                blocks.push(if heading {
+                    parse_block::<text::Heading>(tokens, cx)?
+                } else {
+                    parse_block::<text::Paragraph>(tokens, cx)?
+                });
+                i = end;
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+impl Block {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Heading(h) => h.span(),
+            Self::Paragraph(p) => p.span(),
+            Self::Code(c) => c.span(),
+            Self::List(l) => l.span(),
+            Self::Quote(q) => q.span(),
+            Self::Reference(r) => r.span(),
+            Self::Html(h) => h.span(),
+            Self::Admonition(a) => a.span(),
+            Self::ThematicBreak(t) => t.span(),
+        }
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Block {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Heading(h) => h.to_string(),
+            Self::Paragraph(p) => p.to_string(),
+            Self::Code(c) => c.to_string(),
+            Self::List(l) => l.to_string(),
+            Self::Quote(q) => q.to_string(),
+            Self::Reference(r) => r.to_string(),
+            Self::Html(h) => h.to_string(),
+            Self::Admonition(a) => a.to_string(),
+            Self::ThematicBreak(t) => t.to_string(),
+        }
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Document {
+    fn to_string(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|b| b.to_string())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for text::Heading {
+    fn into(self) -> Block {
+        Block::Heading(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for text::Paragraph {
+    fn into(self) -> Block {
+        Block::Paragraph(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for block::Code {
+    fn into(self) -> Block {
+        Block::Code(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for list::Element {
+    fn into(self) -> Block {
+        Block::List(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for block::Quote {
+    fn into(self) -> Block {
+        Block::Quote(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for text::Reference {
+    fn into(self) -> Block {
+        Block::Reference(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for block::Admonition {
+    fn into(self) -> Block {
+        Block::Admonition(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for block::ThematicBreak {
+    fn into(self) -> Block {
+        Block::ThematicBreak(self)
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Block> for block::Html {
+    fn into(self) -> Block {
+        Block::Html(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parser {
+    tab_width: usize,
+    extensions: text::Extensions,
+}
+
+impl Parser {
+    /// Sets the tab stop used when expanding tabs in the indentation of a line.
+    pub fn tab_width(mut self, n: usize) -> Self {
+        self.tab_width = n.max(1);
+        self
+    }
+    /// Sets the inline syntax extensions to recognise on top of CommonMark.
+    pub fn extensions(mut self, e: text::Extensions) -> Self {
+        self.extensions = e;
+        self
+    }
+    /// Parses `src` without giving up on the first error. Blocks that cannot be
+    /// parsed are kept as paragraphs of their literal text, and the errors
+    /// that caused it are returned alongside the document as warnings.
+    pub fn parse_lenient(&self, src: &str) -> (Document, Vec<crate::ParseError>) {
+        let mut cx = self.context();
+        cx.lenient = true;
+        let tokens = crate::tokenize_tabs(src, self.tab_width);
+        let blocks =
+            parse_document(&tokens, &mut cx).expect("lenient parsing reports errors as warnings");
+        let warnings = cx
+            .warnings
+            .into_iter()
+            .map(|e| e.with_source(src))
+            .collect();
+        (
+            Document {
+                blocks: blocks.into_boxed_slice(),
+            },
+            warnings,
+        )
+    }
+    fn context(&self) -> crate::Context {
+        crate::Context {
+            extensions: self.extensions,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            extensions: text::Extensions::default(),
+        }
+    }
+}
+
+impl crate::Parser<Document, &str> for Parser {
+    fn parse(&self, src: &str) -> Result<Document, crate::ParseError> {
+        crate::parse_tokens_with(
+            &crate::tokenize_tabs(src, self.tab_width),
+            &mut self.context(),
+        )
+        .map_err(|e| e.with_source(src))
+    }
+}
+
+impl crate::Parser<Document, String> for Parser {
+    fn parse(&self, src: String) -> Result<Document, crate::ParseError> {
+        self.parse(src.as_str())
+    }
+}
+
+impl<R: Read + ?Sized> crate::Parser<Document, &mut R> for Parser {
+    fn parse(&self, src: &mut R) -> Result<Document, crate::ParseError> {
+        let mut s = String::new();
+        src.read_to_string(&mut s)?;
+        self.parse(s.as_str())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Builder {
+    blocks: Vec<Block>,
+}
+
+impl Builder {
+    pub fn push(mut self, block: Block) -> Self {
+        self.blocks.push(block);
+        self
+    }
+}
+
+impl crate::Builder for Builder {
+    type Output = Document;
+    fn build(self) -> Result<Self::Output, crate::Error> {
+        Ok(Self::Output {
+            blocks: self.blocks.into_boxed_slice(),
+        })
+    }
+}
+
+impl FromStr for Document {
+    type Err = crate::ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::Parser::parse(&Parser::default(), s)
+    }
+}
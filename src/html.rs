@@ -0,0 +1,303 @@
+use crate::block::HtmlKind;
+
+/// The tags whose contents are kept as they are up to their closing tag, even
+/// across blank lines.
+const RAW_TAGS: [&str; 4] = ["pre", "script", "style", "textarea"];
+
+/// The tags that start an HTML block which may interrupt a paragraph.
+const BLOCK_TAGS: [&str; 62] = [
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+/// An opening or closing HTML tag, such as `<img width="80">` or `</details>`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tag {
+    name: Box<str>,
+    attributes: Box<[Attribute]>,
+    closing: bool,
+    self_closing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Attribute {
+    name: Box<str>,
+    value: Option<Box<str>>,
+}
+
+impl Tag {
+    /// Reads the tag at the start of `s`.
+    pub fn parse(s: &str) -> Option<Self> {
+        tag(s).map(|(tag, _)| tag)
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+    /// The value of the attribute called `name`, compared regardless of case.
+    /// Attributes without a value have an empty one.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+            .map(|a| a.value().unwrap_or(""))
+    }
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+    /// Whether the tag closes itself, as in `<br/>`.
+    pub fn is_self_closing(&self) -> bool {
+        self.self_closing
+    }
+}
+
+impl Attribute {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The value of the attribute without its quotes, if it has one.
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+}
+
+/// Reads the tag at the start of `s`, returning it with its length.
+fn tag(s: &str) -> Option<(Tag, usize)> {
+    let b = s.as_bytes();
+    let mut i = 1;
+    if b.first() != Some(&b'<') {
+        return None;
+    }
+    let closing = b.get(1) == Some(&b'/');
+    if closing {
+        i += 1;
+    }
+    let name_len = name(
+        &s[i..],
+        |c| c.is_ascii_alphabetic(),
+        |c| c.is_ascii_alphanumeric() || c == '-',
+    )?;
+    let tag_name = &s[i..i + name_len];
+    i += name_len;
+    let mut attributes = Vec::new();
+    if !closing {
+        loop {
+            let j = skip_space(s, i);
+            let attr = match name(
+                &s[j..],
+                |c| c.is_ascii_alphabetic() || matches!(c, '_' | ':'),
+                |c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':' | '-'),
+            ) {
+                Some(len) if j > i => len,
+                _ => break,
+            };
+            let attr_name = &s[j..j + attr];
+            i = j + attr;
+            let eq = skip_space(s, i);
+            let mut value = None;
+            if b.get(eq) == Some(&b'=') {
+                let start = skip_space(s, eq + 1);
+                let (v, len) = attribute_value(&s[start..])?;
+                value = Some(v.into());
+                i = start + len;
+            }
+            attributes.push(Attribute {
+                name: attr_name.into(),
+                value,
+            });
+        }
+    }
+    i = skip_space(s, i);
+    let self_closing = !closing && b.get(i) == Some(&b'/');
+    if self_closing {
+        i += 1;
+    }
+    if b.get(i) != Some(&b'>') {
+        return None;
+    }
+    let tag = Tag {
+        name: tag_name.into(),
+        attributes: attributes.into_boxed_slice(),
+        closing,
+        self_closing,
+    };
+    Some((tag, i + 1))
+}
+
+/// The length of the name at the start of `s`, made of a `first` character
+/// and any number of `rest` ones.
+fn name(s: &str, first: impl Fn(char) -> bool, rest: impl Fn(char) -> bool) -> Option<usize> {
+    let mut chars = s.chars();
+    if !chars.next().is_some_and(first) {
+        return None;
+    }
+    Some(1 + chars.take_while(|c| rest(*c)).count())
+}
+
+/// Reads the quoted or unquoted attribute value at the start of `s`,
+/// returning it without quotes along with its length in `s`.
+fn attribute_value(s: &str) -> Option<(&str, usize)> {
+    match s.chars().next()? {
+        q @ ('"' | '\'') => {
+            let end = s[1..].find(q)?;
+            Some((&s[1..end + 1], end + 2))
+        }
+        _ => {
+            let len = s
+                .find(|c: char| c.is_whitespace() || "\"'=<>`".contains(c))
+                .unwrap_or(s.len());
+            (len > 0).then(|| (&s[..len], len))
+        }
+    }
+}
+
+#[inline]
+fn skip_space(s: &str, i: usize) -> usize {
+    i + s[i..].len() - s[i..].trim_start().len()
+}
+
+/// The length of the raw HTML at the start of `s`: a tag, a comment, a
+/// processing instruction, a declaration or a CDATA section.
+pub(crate) fn inline_len(s: &str) -> Option<usize> {
+    let until = |start: usize, end: &str| s[start..].find(end).map(|i| start + i + end.len());
+    if let Some(rest) = s.strip_prefix("<!--") {
+        if rest.starts_with('>') {
+            return Some(5);
+        }
+        if rest.starts_with("->") {
+            return Some(6);
+        }
+        return until(4, "-->");
+    }
+    if s.starts_with("<?") {
+        return until(2, "?>");
+    }
+    if s.starts_with("<![CDATA[") {
+        return until(9, "]]>");
+    }
+    if s.starts_with("<!") && s[2..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return until(2, ">");
+    }
+    tag(s).map(|(_, len)| len)
+}
+
+/// The kind of HTML block started by `line`, with its indentation taken off,
+/// following the start conditions of CommonMark.
+pub(crate) fn block_start(line: &str) -> Option<HtmlKind> {
+    let lower = line.to_ascii_lowercase();
+    let ends_name = |rest: &str| rest.is_empty() || rest.starts_with([' ', '\t', '>']);
+    if RAW_TAGS.iter().any(|t| {
+        lower
+            .strip_prefix('<')
+            .and_then(|l| l.strip_prefix(t))
+            .is_some_and(ends_name)
+    }) {
+        return Some(HtmlKind::Raw);
+    }
+    if lower.starts_with("<!--") {
+        return Some(HtmlKind::Comment);
+    }
+    if lower.starts_with("<?") {
+        return Some(HtmlKind::ProcessingInstruction);
+    }
+    if lower.starts_with("<![cdata[") {
+        return Some(HtmlKind::Cdata);
+    }
+    if lower.starts_with("<!") && lower[2..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Some(HtmlKind::Declaration);
+    }
+    let name = lower.strip_prefix("</").or(lower.strip_prefix('<'))?;
+    if BLOCK_TAGS.iter().any(|t| {
+        name.strip_prefix(t)
+            .is_some_and(|rest| ends_name(rest) || rest.starts_with("/>"))
+    }) {
+        return Some(HtmlKind::Block);
+    }
+    match tag(line) {
+        Some((tag, len))
+            if line[len..].trim().is_empty()
+                && !RAW_TAGS.contains(&tag.name.to_ascii_lowercase().as_str()) =>
+        {
+            Some(HtmlKind::Tag)
+        }
+        _ => None,
+    }
+}
+
+/// Whether `line` holds the end of an HTML block of `kind`. Blocks of tags
+/// end at a blank line instead, which is not part of them.
+pub(crate) fn block_end(kind: HtmlKind, line: &str) -> bool {
+    let lower = line.to_ascii_lowercase();
+    match kind {
+        HtmlKind::Raw => RAW_TAGS
+            .iter()
+            .any(|t| lower.contains(&format!("</{}>", t))),
+        HtmlKind::Comment => lower.contains("-->"),
+        HtmlKind::ProcessingInstruction => lower.contains("?>"),
+        HtmlKind::Declaration => lower.contains('>'),
+        HtmlKind::Cdata => lower.contains("]]>"),
+        HtmlKind::Block | HtmlKind::Tag => line.trim().is_empty(),
+    }
+}
@@ -1,15 +1,24 @@
 pub mod block;
+pub mod document;
+mod entities;
+pub mod html;
 pub mod list;
 pub mod text;
 
 #[cfg(test)]
 mod tests;
 
+use std::collections::HashSet;
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
 use std::slice::Iter;
+use std::str::Chars;
+use std::sync::Arc;
 use url::ParseError as ParseErrorUrl;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ParseError {
+pub enum ParseErrorKind {
     EmptyDocument,
     EmptyContent,
     UnexpectedChar(char),
@@ -18,6 +27,64 @@ pub enum ParseError {
     UnexpectedEnd,
     InvalidUrl(ParseErrorUrl),
     IncompleteBuilderData,
+    InvalidBuilderData,
+    Io(IoError),
+    /// Quotes, admonitions and lists nested more than [`MAX_DEPTH`] deep.
+    TooDeep,
+}
+
+/// How deep quotes, admonitions and lists may be nested in one another.
+pub const MAX_DEPTH: usize = 64;
+
+/// The I/O error a source could not be read because of, shared so that parse
+/// errors stay cheap to clone. Like [`ParseError`], two are equal when they are
+/// of the same kind.
+#[derive(Debug, Clone)]
+pub struct IoError(Arc<std::io::Error>);
+
+impl IoError {
+    pub fn kind(&self) -> std::io::ErrorKind {
+        self.0.kind()
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
+impl Eq for IoError {}
+
+impl From<std::io::Error> for IoError {
+    fn from(value: std::io::Error) -> Self {
+        Self(Arc::new(value))
+    }
+}
+
+impl From<std::io::ErrorKind> for IoError {
+    fn from(value: std::io::ErrorKind) -> Self {
+        std::io::Error::from(value).into()
+    }
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An error raised while parsing, along with where it happened and what the
+/// parser would have accepted there instead.
+///
+/// Like the spans of nodes, the location and the expected set take no part in
+/// comparisons: two errors are equal when they are of the same kind.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    span: Option<Span>,
+    expected: Box<[Box<str>]>,
+    line: Option<Box<str>>,
 }
 
 pub trait Builder: Default {
@@ -26,39 +93,367 @@ pub trait Builder: Default {
 }
 
 pub(crate) trait Element: ToString + Sized {
-    fn parse(iter: &mut Iter<ParseToken>) -> Result<Self, ParseError>;
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, ParseError>;
+    /// Like [`Element::parse`], but following the settings in `cx`. Elements
+    /// that can keep going past constructs they fail to parse record the
+    /// errors there.
+    fn parse_with(iter: &mut Iter<Token>, cx: &mut Context) -> Result<Self, ParseError> {
+        let _ = cx;
+        Self::parse(iter)
+    }
+}
+
+/// Settings and state shared by the parsers of the elements of a document.
+#[derive(Debug, Default)]
+pub(crate) struct Context {
+    /// The inline syntax extensions to recognise.
+    pub(crate) extensions: text::Extensions,
+    /// Whether blocks that fail to parse are kept as literal text.
+    pub(crate) lenient: bool,
+    /// The errors that parsing recovered from.
+    pub(crate) warnings: Vec<ParseError>,
+    /// The normalized labels of the reference definitions in the document,
+    /// which shortcut reference links have to match.
+    pub(crate) references: HashSet<String>,
+    /// How many blocks the one being parsed is nested in.
+    pub(crate) depth: usize,
+}
+
+impl Context {
+    /// Runs `f` to parse the content of a block nested one level deeper.
+    pub(crate) fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(ParseErrorKind::TooDeep.into());
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Error {
     IncompleteData,
+    InvalidData,
+}
+
+pub trait Parser<Out, Src> {
+    fn parse(&self, src: Src) -> Result<Out, ParseError>;
+}
+
+impl ParseError {
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+    /// Where in the source the error was found. Errors that do not come from
+    /// the source text, such as I/O errors, have no span.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+    /// Descriptions of what would have been accepted where the error was
+    /// found, such as `` `]` `` or `end of input`.
+    pub fn expected(&self) -> &[Box<str>] {
+        &self.expected
+    }
+    pub(crate) fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+    pub(crate) fn with_expected(mut self, expected: &[&str]) -> Self {
+        let mut all = self.expected.into_vec();
+        for e in expected {
+            if !all.iter().any(|x| **x == **e) {
+                all.push((*e).into());
+            }
+        }
+        self.expected = all.into_boxed_slice();
+        self
+    }
+    /// Keeps the source line the error points at, so that it can be shown when
+    /// the error is displayed.
+    pub(crate) fn with_source(mut self, src: &str) -> Self {
+        if let Some(span) = self.span {
+            let offset = span.start.offset.min(src.len());
+            let start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+            let end = src[offset..].find('\n').map_or(src.len(), |i| offset + i);
+            self.line = Some(src[start..end].trim_end_matches('\r').into());
+        }
+        self
+    }
+    /// Fills in the span of an error raised without one, from the tokens that
+    /// had been consumed from `tokens` when it was raised.
+    pub(crate) fn locate(self, tokens: &[Token], iter: &Iter<Token>) -> Self {
+        if self.span.is_some() {
+            return self;
+        }
+        let consumed = tokens.len() - iter.as_slice().len();
+        let span = match (consumed.checked_sub(1).map(|i| &tokens[i]), &self.kind) {
+            (Some(last), ParseErrorKind::UnexpectedEnd) => Span::new(last.span.end, last.span.end),
+            (Some(last), _) => last.span,
+            (None, _) => match tokens.first() {
+                Some(first) => first.span,
+                None => Span::new(Position::START, Position::START),
+            },
+        };
+        self.with_span(span)
+    }
+}
+
+impl PartialEq for ParseError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for ParseError {}
+
+impl From<ParseErrorKind> for ParseError {
+    fn from(kind: ParseErrorKind) -> Self {
+        Self {
+            kind,
+            span: None,
+            expected: Box::default(),
+            line: None,
+        }
+    }
 }
 
-pub trait Parser<Out, Src = &'static str> {
-    fn parse(src: Src) -> Result<Out, ParseError>;
+impl From<std::io::Error> for ParseError {
+    fn from(value: std::io::Error) -> Self {
+        ParseErrorKind::Io(value.into()).into()
+    }
 }
 
 impl From<crate::Error> for ParseError {
     fn from(value: crate::Error) -> Self {
         match value {
-            crate::Error::IncompleteData => Self::IncompleteBuilderData,
+            crate::Error::IncompleteData => ParseErrorKind::IncompleteBuilderData.into(),
+            crate::Error::InvalidData => ParseErrorKind::InvalidBuilderData.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyDocument => write!(f, "empty document"),
+            Self::EmptyContent => write!(f, "empty content"),
+            Self::UnexpectedChar('\n') => write!(f, "unexpected line break"),
+            Self::UnexpectedChar(c) => write!(f, "unexpected character `{}`", c),
+            Self::UnexpectedString(s) => write!(f, "unexpected `{}`", s),
+            Self::UnexpectedNumber(p, d) => write!(
+                f,
+                "unexpected number `{}`",
+                ParseToken::Number(*p, *d).to_string()
+            ),
+            Self::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Self::InvalidUrl(e) => write!(f, "invalid URL: {}", e),
+            Self::IncompleteBuilderData => write!(f, "incomplete builder data"),
+            Self::InvalidBuilderData => write!(f, "invalid builder data"),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::TooDeep => write!(f, "blocks nested more than {} deep", MAX_DEPTH),
+        }
+    }
+}
+
+/// Renders the error the way a compiler would: the message, its location and,
+/// when the source is known, the offending line with the span underlined.
+///
+/// ```text
+/// unexpected character `?`
+///  --> 1:14
+///   |
+/// 1 | **bold text**?
+///   |              ^ expected end of input
+/// ```
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        let expected = match &*self.expected {
+            [] => String::new(),
+            [one] => format!("expected {}", one),
+            [init @ .., last] => format!("expected {} or {}", init.join(", "), last),
+        };
+        let span = match self.span {
+            Some(span) => span,
+            None if expected.is_empty() => return Ok(()),
+            None => return write!(f, ", {}", expected),
+        };
+        let (line, column) = (span.start.line, span.start.column);
+        writeln!(f)?;
+        let gutter = " ".repeat(line.to_string().len());
+        write!(f, "{}--> {}:{}", gutter, line, column)?;
+        let Some(text) = &self.line else {
+            if !expected.is_empty() {
+                write!(f, "\n{} = {}", gutter, expected)?;
+            }
+            return Ok(());
+        };
+        let lead: String = text
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let rest = text.chars().count().saturating_sub(column - 1);
+        let width = if span.end.line == line {
+            span.end.column.saturating_sub(column)
+        } else {
+            rest
+        };
+        let carets = "^".repeat(width.clamp(1, rest.max(1)));
+        write!(
+            f,
+            "\n{} |\n{} | {}\n{} | {}{}",
+            gutter, line, text, gutter, lead, carets
+        )?;
+        if !expected.is_empty() {
+            write!(f, " {}", expected)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::InvalidUrl(e) => Some(e),
+            ParseErrorKind::Io(e) => Some(&*e.0),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncompleteData => write!(f, "incomplete data"),
+            Self::InvalidData => write!(f, "invalid data"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A location in the source text. Lines and columns start at 1 and columns
+/// count characters, not bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    pub(crate) const START: Position = Position {
+        offset: 0,
+        line: 1,
+        column: 1,
+    };
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> usize {
+        self.column
+    }
+    pub(crate) fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
         }
     }
 }
 
+/// The region of the source text a token or node was parsed from.
+///
+/// Nodes leave their spans out of comparisons: two nodes are equal when their
+/// content is, wherever they came from. Nodes made with a builder carry the
+/// default span, whose positions are all zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    start: Position,
+    end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+    pub fn start(&self) -> Position {
+        self.start
+    }
+    pub fn end(&self) -> Position {
+        self.end
+    }
+    pub fn line(&self) -> usize {
+        self.start.line
+    }
+    pub fn column(&self) -> usize {
+        self.start.column
+    }
+    /// The byte range of the span in the source text.
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+    pub fn len(&self) -> usize {
+        self.end.offset - self.start.offset
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Joins two spans into one running from the start of `self` to the end
+    /// of `end`.
+    pub fn to(self, end: Span) -> Span {
+        Span::new(self.start, end.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Token {
+    pub(crate) kind: ParseToken,
+    pub(crate) span: Span,
+}
+
+impl PartialEq<Token> for ParseToken {
+    fn eq(&self, other: &Token) -> bool {
+        *self == other.kind
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Token {
+    fn to_string(&self) -> String {
+        self.kind.to_string()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
-pub(self) enum ParseToken {
+pub(crate) enum ParseToken {
     RepeatSpecial(char, usize),
     String(String),
     Number(usize, Option<usize>),
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for ParseToken {
     fn to_string(&self) -> String {
         match self {
             Self::Number(p, d) => {
-                if let Some(f) = d {
+                if let Some(f) = d.filter(|f| *f != 0) {
                     format!("{}.{}", p, f)
+                } else if d.is_some() {
+                    format!("{}.", p)
                 } else {
                     p.to_string()
                 }
@@ -69,23 +464,37 @@ impl ToString for ParseToken {
     }
 }
 
+/// Implements equality and ordering for a node from the given fields, which
+/// leave out its span.
+#[macro_export]
+macro_rules! impl_content_eq {
+    ($type:ident { $($field:ident),+ }) => {
+        impl PartialEq for $type {
+            fn eq(&self, other: &Self) -> bool {
+                ($(&self.$field,)+) == ($(&other.$field,)+)
+            }
+        }
+        impl Eq for $type {}
+        impl PartialOrd for $type {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for $type {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                ($(&self.$field,)+).cmp(&($(&other.$field,)+))
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! impl_from_str {
     ($type:ident) => {
         impl std::str::FromStr for $type {
-            type Err = crate::ParseError;
+            type Err = $crate::ParseError;
             fn from_str(s: &str) -> Result<Self, Self::Err> {
-                use crate::Element;
-                let tokens = crate::tokenize(s);
-                let mut iter = tokens.iter();
-                let val = {
-                    match Self::parse(&mut iter) {
-                        Ok(t) => t,
-                        Err(e) => return Err(e),
-                    }
-                };
-                crate::token_expect!(iter);
-                return Ok(val);
+                $crate::parse_tokens(&$crate::tokenize(s)).map_err(|e| e.with_source(s))
             }
         }
     };
@@ -95,291 +504,319 @@ macro_rules! impl_from_str {
 macro_rules! token_expect {
     ($iter:ident) => {
         if let Some(t) = $iter.next() {
-            return match t {
-                crate::ParseToken::String(s) => {
-                    Err(crate::ParseError::UnexpectedString(s.to_owned()))
-                }
-                crate::ParseToken::RepeatSpecial(c, _) => {
-                    Err(crate::ParseError::UnexpectedChar(*c))
-                }
-                crate::ParseToken::Number(p, d) => {
-                    let s = if let Some(f) = d {
-                        format!("{}.{}", p, f)
-                    } else {
-                        p.to_string()
-                    };
-                    Err(crate::ParseError::UnexpectedString(s))
-                }
-            };
+            return Err($crate::unexpected(t).with_expected(&["end of input"]));
         }
     };
     ($iter:ident, $char:literal, $num:literal) => {
-        if let Some(t) = $iter.next() {
-            match t {
-                ParseToken::RepeatSpecial($char, $num) => {}
-                ParseToken::RepeatSpecial(c, _) => {
-                    return Err(crate::ParseError::UnexpectedChar(*c))
-                }
-                ParseToken::String(s) => {
-                    return Err(crate::ParseError::UnexpectedString(s.to_owned()))
-                }
-                ParseToken::Number(_, _) => {
-                    return Err(crate::ParseError::UnexpectedString(t.to_string()))
-                }
+        match $iter.next() {
+            Some(t) if t.kind == $crate::ParseToken::RepeatSpecial($char, $num) => {}
+            t => {
+                let expected = format!("`{}`", $char.to_string().repeat($num));
+                return Err($crate::unexpected_or_end(t).with_expected(&[&expected]));
             }
-        } else {
-            return Err(crate::ParseError::UnexpectedEnd);
         }
     };
     ($iter:ident, $char:literal) => {
-        if let Some(t) = $iter.next() {
-            match t {
-                ParseToken::RepeatSpecial($char, _) => {}
-                ParseToken::RepeatSpecial(c, _) => {
-                    return Err(crate::ParseError::UnexpectedChar(*c))
-                }
-                ParseToken::String(s) => {
-                    return Err(crate::ParseError::UnexpectedString(s.to_owned()))
-                }
+        match $iter.next() {
+            Some(t) if matches!(t.kind, $crate::ParseToken::RepeatSpecial($char, _)) => {}
+            t => {
+                let expected = format!("`{}`", $char);
+                return Err($crate::unexpected_or_end(t).with_expected(&[&expected]));
             }
-        } else {
-            return Err(crate::ParseError::UnexpectedEnd);
         }
     };
 }
 
-#[macro_export]
-macro_rules! token_ignore_char {
-    ($iter:ident, $char:literal) => {
-        if let Some(t) = $iter.next() {
-            crate::token_ignore_char!($iter, $char, t)
-        } else {
-            return Err(crate::ParseError::UnexpectedEnd);
-        }
-    };
-    ($iter:ident, $char:literal, $tok:ident) => {
-        if let crate::ParseToken::RepeatSpecial($char, _) = $tok {
-            if let Some(t) = $iter.next() {
-                t
-            } else {
-                return Err(crate::ParseError::UnexpectedEnd);
+pub(crate) fn tokenize(s: &str) -> Vec<Token> {
+    let mut pos = Position::START;
+    lex(s)
+        .into_iter()
+        .map(|kind| {
+            let start = pos;
+            pos.advance(&kind.to_string());
+            Token {
+                kind,
+                span: Span::new(start, pos),
             }
-        } else {
-            $tok
+        })
+        .collect()
+}
+
+/// Like [`tokenize`], but with the tabs in the indentation of every line
+/// expanded to spaces up to the next multiple of `tab_width`. The tokens keep
+/// their place in `s` itself; a position inside an expanded tab is that of
+/// the tab.
+pub(crate) fn tokenize_tabs(s: &str, tab_width: usize) -> Vec<Token> {
+    let mut expanded = String::with_capacity(s.len());
+    // For every line, its offset in `s` and in the expanded text, and the
+    // width of each character of its indentation once expanded.
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in s.split('\n') {
+        let rest = line.trim_start_matches([' ', '\t']);
+        let mut widths = Vec::new();
+        let mut col = 0;
+        for c in line[..line.len() - rest.len()].chars() {
+            let n = if c == '\t' {
+                tab_width - col % tab_width
+            } else {
+                1
+            };
+            widths.push(n);
+            col += n;
         }
-    };
-    ($iter:ident, $char:literal, $else:expr) => {
-        if let Some(t) = $iter.next() {
-            crate::token_ignore_char!($iter, $char, t)
-        } else {
-            $else
+        lines.push((offset, expanded.len(), widths));
+        offset += line.len() + 1;
+        expanded.push_str(&" ".repeat(col));
+        expanded.push_str(rest);
+        expanded.push('\n');
+    }
+    expanded.pop();
+    if expanded == s {
+        return tokenize(s);
+    }
+    let original = |p: Position| {
+        let (offset, expanded_offset, widths) = &lines[p.line - 1];
+        let mut col = 0;
+        for (k, w) in widths.iter().enumerate() {
+            if p.column - 1 < col + w {
+                return Position {
+                    offset: offset + k,
+                    column: k + 1,
+                    ..p
+                };
+            }
+            col += w;
         }
-    };
-    ($iter:ident, $char:literal, $num:literal) => {
-        if let Some(t) = $iter.next() {
-            crate::token_ignore_char!($iter, $char, $num, t);
-        } else {
-            return Err(crate::ParseError::UnexpectedEnd);
+        let extra = col - widths.len();
+        Position {
+            offset: p.offset - expanded_offset + offset - extra,
+            column: p.column - extra,
+            ..p
         }
     };
-    ($iter:ident, $char:literal, $num:literal, $tok:ident) => {
-        if let ParseToken::RepeatSpecial($char, $num) = $tok {
-            if let Some(t) = $iter.next() {
-                (t, $num)
+    let mut tokens = tokenize(&expanded);
+    for t in &mut tokens {
+        t.span = Span::new(original(t.span.start), original(t.span.end));
+    }
+    tokens
+}
+
+/// Splits `s` into tokens. Every token renders back to exactly the text it was
+/// read from, which is what lets [`tokenize`] recover positions afterwards.
+fn lex(s: &str) -> Vec<ParseToken> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            let mut digits = String::from(c);
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            let whole = match parse_digits(&digits) {
+                Some(w) if !chars.peek().is_some_and(|n| n.is_alphanumeric()) => w,
+                _ => {
+                    tokens.push(ParseToken::String(take_text(digits, &mut chars)));
+                    continue;
+                }
+            };
+            if chars.peek() != Some(&'.') {
+                tokens.push(ParseToken::Number(whole, None));
+                continue;
+            }
+            let mut ahead = chars.clone();
+            ahead.next();
+            let mut fraction = String::new();
+            while let Some(d) = ahead.next_if(|d| d.is_ascii_digit()) {
+                fraction.push(d);
+            }
+            if fraction.is_empty() {
+                chars.next();
+                tokens.push(ParseToken::Number(whole, Some(0)));
+            } else if let Some(f) = parse_digits(&fraction).filter(|f| *f != 0) {
+                chars = ahead;
+                tokens.push(ParseToken::Number(whole, Some(f)));
             } else {
-                return Err(crate::ParseError::UnexpectedEnd);
+                tokens.push(ParseToken::Number(whole, None));
             }
+        } else if c.is_alphanumeric() {
+            tokens.push(ParseToken::String(take_text(String::from(c), &mut chars)));
         } else {
-            ($tok, 0)
+            let mut n = 1;
+            while chars.next_if_eq(&c).is_some() {
+                n += 1;
+            }
+            tokens.push(ParseToken::RepeatSpecial(c, n));
         }
-    };
+    }
+    tokens
 }
 
-#[macro_export]
-macro_rules! token_combine_except {
-    ($iter:ident, $($clause:pat, $action:expr),+) => {{
-        let mut combined = String::new();
-        while let Some(t) = $iter.next() {
-            match t {
-                $($clause => $action,)+
-                crate::ParseToken::String(s) => combined.push_str(s),
-                crate::ParseToken::RepeatSpecial(c, n) => {
-                    combined.push_str(&c.to_string().repeat(*n))
-                }
-            }
+/// Parses a run of ASCII digits, refusing anything that would not render back
+/// to the same digits (leading zeros or overflow).
+#[inline]
+fn parse_digits(digits: &str) -> Option<usize> {
+    if digits.len() > 1 && digits.starts_with('0') {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+#[inline]
+fn take_text(mut text: String, chars: &mut Peekable<Chars>) -> String {
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == ' ') {
+        text.push(c);
+    }
+    text
+}
+
+/// Collects the rest of the current line as text and consumes the line break
+/// after it, returning the text and the number of line breaks consumed.
+pub(crate) fn take_line(iter: &mut Iter<Token>) -> (String, usize) {
+    let mut line = String::new();
+    for t in iter.by_ref() {
+        match &t.kind {
+            ParseToken::RepeatSpecial('\n', n) => return (line, *n),
+            _ => line.push_str(&t.to_string()),
         }
-        combined
-    }};
-    ($first:ident, $iter:ident, $($clause:pat, $action:expr),+) => {{
-        let mut combined = String::new();
-        let mut item = Some($first);
-        while let Some(t) = item {
-            match t {
-                $($clause => $action,)+
-                crate::ParseToken::String(s) => combined.push_str(s),
-                crate::ParseToken::RepeatSpecial(c, n) => {
-                    combined.push_str(&c.to_string().repeat(*n))
-                },
-                crate::ParseToken::Number(_, _) => combined.push_str(&t.to_string()),
+    }
+    (line, 0)
+}
+
+/// A line of a token stream without its line break, along with the range it
+/// covers in that stream.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Line<'a> {
+    pub(crate) tokens: &'a [Token],
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+/// Splits `tokens` into lines. A run of `n` line breaks yields `n - 1` empty
+/// lines in between.
+pub(crate) fn lines(tokens: &[Token]) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        if let ParseToken::RepeatSpecial('\n', n) = t.kind {
+            lines.push(line(tokens, start, i));
+            for _ in 1..n {
+                lines.push(line(tokens, i, i));
             }
-            item = $iter.next();
+            start = i + 1;
         }
-        combined
-    }};
+    }
+    if start < tokens.len() {
+        lines.push(line(tokens, start, tokens.len()));
+    }
+    lines
 }
 
 #[inline]
-fn process_char(
-    c: char,
-    last_c: &mut char,
-    counter: &mut usize,
-    tokens: &mut Vec<ParseToken>,
-    src: &mut String,
-    nu: &mut bool,
-) {
-    if *last_c == '\0' && src.is_empty() {
-        *last_c = c;
-        return;
-    }
-    if c >= '0' && c <= '9' {
-        if src.is_empty() {
-            if *last_c != '\0' {
-                tokens.push(ParseToken::RepeatSpecial(*last_c, *counter + 1))
-            }
-            src.push(c);
-            *nu = true
-        } else {
-            src.push(c)
-        }
-        *last_c = c;
-        *counter = 0;
-        return;
-    }
-    if *last_c == c {
-        *counter += 1;
-        return;
-    } else if src.is_empty() {
-        tokens.push(ParseToken::RepeatSpecial(*last_c, *counter + 1));
-        *last_c = '\0';
-    } else {
-        let t = if *nu {
-            let mut s = src.split('.');
-            let f = s.nth(0).unwrap().parse().unwrap();
-            let k = if let Some(k) = s.nth(1) {
-                Some(k.parse().unwrap())
-            } else {
-                None
-            };
-            *nu = false;
-            ParseToken::Number(f, k)
-        } else {
-            ParseToken::String(src.clone())
-        };
-        tokens.push(t);
-        src.clear();
+fn line(tokens: &[Token], start: usize, mut end: usize) -> Line<'_> {
+    if end > start && tokens[end - 1].kind == ParseToken::RepeatSpecial('\r', 1) {
+        end -= 1;
+    }
+    Line {
+        tokens: &tokens[start..end],
+        start,
+        end,
     }
-    *last_c = c;
-    *counter = 0;
 }
 
-pub(self) fn tokenize(s: &str) -> Vec<ParseToken> {
-    let mut whole = None;
-    let mut tokens = Vec::new();
-    let mut last_c = '\0';
-    let mut counter: usize = 0;
-    let mut src = String::new();
-    let mut number = false;
-    for c in s.chars() {
-        match c {
-            'A'..='Z' => {
-                if last_c != '\0' && !number {
-                    tokens.push(ParseToken::RepeatSpecial(last_c, counter + 1));
-                    last_c = '\0';
-                }
-                src.push(c)
-            }
-            'a'..='z' => {
-                if last_c != '\0' && !number {
-                    tokens.push(ParseToken::RepeatSpecial(last_c, counter + 1));
-                    last_c = '\0';
-                }
-                src.push(c)
-            }
-            ' ' => {
-                if src.is_empty() {
-                    process_char(
-                        c,
-                        &mut last_c,
-                        &mut counter,
-                        &mut tokens,
-                        &mut src,
-                        &mut number,
-                    )
-                } else {
-                    src.push(' ')
-                }
-            }
-            '0'..='9' => {
-                if last_c != '\0' {
-                    tokens.push(ParseToken::RepeatSpecial(last_c, counter + 1));
-                    last_c = '\0';
-                }
-                if src.is_empty() {
-                    number = true;
-                }
-                src.push(c);
-            }
-            '.' => {
-                if number {
-                    last_c = '\0';
-                    if let Some(w) = whole {
-                        tokens.push(ParseToken::Number(w, Some(src.parse().unwrap())));
-                        whole = None;
-                    } else {
-                        whole = Some(src.parse().unwrap());
-                        src.clear();
-                        continue;
-                    }
+#[inline]
+pub(crate) fn indent(line: &[Token]) -> (usize, &[Token]) {
+    match kind(line, 0) {
+        Some(ParseToken::RepeatSpecial(' ', n)) => (*n, &line[1..]),
+        _ => (0, line),
+    }
+}
+
+#[inline]
+pub(crate) fn is_blank(line: &[Token]) -> bool {
+    line.iter()
+        .all(|t| matches!(t.kind, ParseToken::RepeatSpecial(' ' | '\t', _)))
+}
+
+/// Copies `tokens`, taking up to `width` spaces of indentation off the start
+/// of every line. The tokens that are left keep their place in the source.
+pub(crate) fn dedent(tokens: &[Token], width: usize) -> Vec<Token> {
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut line_start = true;
+    for t in tokens {
+        match t.kind {
+            ParseToken::RepeatSpecial(' ', n) if line_start => {
+                let cut = n.min(width);
+                if n > cut {
+                    let mut start = t.span.start;
+                    start.advance(&" ".repeat(cut));
+                    out.push(Token {
+                        kind: ParseToken::RepeatSpecial(' ', n - cut),
+                        span: Span::new(start, t.span.end),
+                    });
                 }
-                process_char(
-                    c,
-                    &mut last_c,
-                    &mut counter,
-                    &mut tokens,
-                    &mut src,
-                    &mut number,
-                )
             }
-            _ => process_char(
-                c,
-                &mut last_c,
-                &mut counter,
-                &mut tokens,
-                &mut src,
-                &mut number,
-            ),
+            _ => out.push(t.clone()),
         }
+        line_start = matches!(t.kind, ParseToken::RepeatSpecial('\n', _));
     }
-    if number {
-        let (w, b) = if let Some(w) = whole {
-            (w, true)
-        } else {
-            (src.parse().unwrap(), false)
-        };
-        let d = if src.is_empty() {
-            if b {
-                Some(0)
-            } else {
-                None
-            }
-        } else {
-            Some(src.parse().unwrap())
-        };
-        tokens.push(ParseToken::Number(w, d))
-    } else if last_c != '\0' {
-        tokens.push(ParseToken::RepeatSpecial(last_c, counter + 1))
-    } else if !src.is_empty() {
-        tokens.push(ParseToken::String(src))
+    out
+}
+
+/// Parses `tokens` as a single `T`, failing if anything is left over.
+pub(crate) fn parse_tokens<T: Element>(tokens: &[Token]) -> Result<T, ParseError> {
+    parse_tokens_with(tokens, &mut Context::default())
+}
+
+/// Like [`parse_tokens`], following the settings in `cx`.
+pub(crate) fn parse_tokens_with<T: Element>(
+    tokens: &[Token],
+    cx: &mut Context,
+) -> Result<T, ParseError> {
+    let mut iter = tokens.iter();
+    let result = T::parse_with(&mut iter, cx).and_then(|val| {
+        token_expect!(iter);
+        Ok(val)
+    });
+    result.map_err(|e| e.locate(tokens, &iter))
+}
+
+/// The error for finding `t` where something else was expected, located at
+/// `t`.
+pub(crate) fn unexpected(t: &Token) -> ParseError {
+    let kind = match &t.kind {
+        ParseToken::RepeatSpecial(c, _) => ParseErrorKind::UnexpectedChar(*c),
+        ParseToken::String(s) => ParseErrorKind::UnexpectedString(s.to_owned()),
+        ParseToken::Number(_, _) => ParseErrorKind::UnexpectedString(t.to_string()),
+    };
+    ParseError::from(kind).with_span(t.span)
+}
+
+/// Like [`unexpected`], but for a token that may be missing altogether.
+pub(crate) fn unexpected_or_end(t: Option<&Token>) -> ParseError {
+    t.map_or(ParseErrorKind::UnexpectedEnd.into(), unexpected)
+}
+
+/// The kind of the token at `i` in `tokens`, if there is one.
+#[inline]
+pub(crate) fn kind(tokens: &[Token], i: usize) -> Option<&ParseToken> {
+    tokens.get(i).map(|t| &t.kind)
+}
+
+/// The span covered by `tokens`, leaving out trailing line breaks.
+pub(crate) fn span_of(tokens: &[Token]) -> Span {
+    let last = tokens
+        .iter()
+        .rposition(|t| !matches!(t.kind, ParseToken::RepeatSpecial('\n', _)));
+    match (tokens.first(), last) {
+        (Some(first), Some(last)) => first.span.to(tokens[last].span),
+        (Some(first), None) => Span::new(first.span.start, first.span.start),
+        _ => Span::default(),
     }
-    return tokens;
+}
+
+/// The span of the tokens consumed from `before` to reach `iter`.
+pub(crate) fn consumed(before: &[Token], iter: &Iter<Token>) -> Span {
+    span_of(&before[..before.len() - iter.as_slice().len()])
 }
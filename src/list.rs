@@ -1,97 +1,663 @@
 use std::slice::Iter;
 
-use crate::ParseToken;
+use crate::document::Block;
+use crate::{ParseToken, Span, Token};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub struct Item {
     content: Box<str>,
-    indented: Box<[Box<str>]>,
+    items: Box<[crate::text::Item]>,
+    blocks: Box<[Block]>,
     ordered: bool,
+    checked: Option<bool>,
+    span: Span,
 }
 
+crate::impl_content_eq!(Item {
+    content,
+    blocks,
+    ordered,
+    checked
+});
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Element {
     Ordered(Ordered),
     Unordered(Unordered),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub struct Ordered {
     items: Box<[Item]>,
+    start: usize,
+    delimiter: char,
+    repeated: bool,
+    tight: bool,
+    span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+crate::impl_content_eq!(Ordered {
+    items,
+    start,
+    delimiter,
+    repeated,
+    tight
+});
+
+#[derive(Debug, Clone)]
 pub struct Unordered {
     items: Box<[Item]>,
+    bullet: char,
+    tight: bool,
+    span: Span,
 }
 
+crate::impl_content_eq!(Unordered {
+    items,
+    bullet,
+    tight
+});
+
 #[derive(Debug, Default, Clone)]
 pub struct ItemBuilder {
     content: String,
-    indented: Vec<Box<str>>,
+    blocks: Vec<Block>,
     ordered: bool,
+    checked: Option<bool>,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct Builder {
     items: Vec<Item>,
     ordered: bool,
+    start: usize,
+    delimiter: char,
+    repeated: bool,
+    bullet: char,
+    tight: bool,
 }
 
-impl super::Element for Ordered {
-    fn parse(iter: &mut Iter<crate::ParseToken>) -> Result<Self, crate::ParseError> {
-        crate::token_ignore_char!(iter, ' ');
-        if let Some(t) = iter.next() {
-            match t {
-                ParseToken::Number(p, n) => todo!(),
-                ParseToken::RepeatSpecial(c, _) => Err(crate::ParseError::UnexpectedChar(*c)),
-                ParseToken::String(s) => Err(crate::ParseError::UnexpectedString(s.to_owned())),
+impl Item {
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+    /// The content of the item parsed as inline text.
+    pub fn items(&self) -> &[crate::text::Item] {
+        &self.items
+    }
+    /// The blocks of the item after the paragraph it starts with, nested
+    /// lists included.
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+    /// The lists nested under the item.
+    pub fn children(&self) -> Vec<&Element> {
+        self.blocks
+            .iter()
+            .filter_map(|b| match b {
+                Block::List(l) => Some(l),
+                _ => None,
+            })
+            .collect()
+    }
+    pub fn children_mut(&mut self) -> Vec<&mut Element> {
+        self.blocks
+            .iter_mut()
+            .filter_map(|b| match b {
+                Block::List(l) => Some(l),
+                _ => None,
+            })
+            .collect()
+    }
+    /// The state of the item's checkbox, or `None` if it is not a task.
+    pub fn checked(&self) -> Option<bool> {
+        self.checked
+    }
+    pub fn is_task(&self) -> bool {
+        self.checked.is_some()
+    }
+    /// Checks the item if it is unchecked and the other way round, returning
+    /// the new state. Items that are not tasks are left alone.
+    pub fn toggle(&mut self) -> Option<bool> {
+        self.checked = self.checked.map(|c| !c);
+        self.checked
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl Ordered {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+    /// The number of the first item.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    /// The character after each number, `.` or `)`.
+    pub fn delimiter(&self) -> char {
+        self.delimiter
+    }
+    /// Whether every item repeats the number of the first rather than
+    /// counting up from it, as in `1. a` followed by `1. b`.
+    pub fn is_repeated(&self) -> bool {
+        self.repeated
+    }
+    /// Whether the items are not separated by blank lines.
+    pub fn is_tight(&self) -> bool {
+        self.tight
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl Unordered {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+    /// The bullet before each item, `-`, `*` or `+`.
+    pub fn bullet(&self) -> char {
+        self.bullet
+    }
+    /// Whether the items are not separated by blank lines.
+    pub fn is_tight(&self) -> bool {
+        self.tight
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl Element {
+    pub fn items(&self) -> &[Item] {
+        match self {
+            Self::Ordered(l) => l.items(),
+            Self::Unordered(l) => l.items(),
+        }
+    }
+    pub fn items_mut(&mut self) -> &mut [Item] {
+        match self {
+            Self::Ordered(l) => &mut l.items,
+            Self::Unordered(l) => &mut l.items,
+        }
+    }
+    /// Every task item of the list, nested ones included, in document order.
+    pub fn tasks(&self) -> Vec<&Item> {
+        let mut tasks = Vec::new();
+        self.collect_tasks(&mut tasks);
+        tasks
+    }
+    pub(crate) fn collect_tasks<'a>(&'a self, tasks: &mut Vec<&'a Item>) {
+        for item in self.items() {
+            if item.is_task() {
+                tasks.push(item);
+            }
+            for child in item.children() {
+                child.collect_tasks(tasks);
             }
-        } else {
-            Err(crate::ParseError::UnexpectedEnd)
+        }
+    }
+    pub fn is_tight(&self) -> bool {
+        match self {
+            Self::Ordered(l) => l.is_tight(),
+            Self::Unordered(l) => l.is_tight(),
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Ordered(l) => l.span(),
+            Self::Unordered(l) => l.span(),
         }
     }
 }
 
+impl super::Element for Ordered {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
+    }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        let (items, markers, tight) = parse_items(iter, true, cx)?;
+        let (start, delimiter) = match markers[0] {
+            Marker::Number(n, d) => (n, d),
+            Marker::Bullet(_) => unreachable!(),
+        };
+        let repeated = markers.len() > 1 && markers.iter().all(|m| *m == markers[0]);
+        Ok(Ordered {
+            items: items.into_boxed_slice(),
+            start,
+            delimiter,
+            repeated,
+            tight,
+            span: crate::consumed(before, iter),
+        })
+    }
+}
+
 impl super::Element for Unordered {
-    fn parse(iter: &mut Iter<crate::ParseToken>) -> Result<Self, crate::ParseError> {
-        crate::token_ignore_char!(iter, ' ');
-        todo!("Implement")
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
+    }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        let (items, markers, tight) = parse_items(iter, false, cx)?;
+        let bullet = match markers[0] {
+            Marker::Bullet(c) => c,
+            Marker::Number(_, _) => unreachable!(),
+        };
+        Ok(Unordered {
+            items: items.into_boxed_slice(),
+            bullet,
+            tight,
+            span: crate::consumed(before, iter),
+        })
     }
 }
 
 impl super::Element for Element {
-    fn parse(iter: &mut Iter<crate::ParseToken>) -> Result<Self, crate::ParseError> {
-        crate::token_ignore_char!(iter, ' ');
-        todo!("Implement")
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
     }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let tokens = iter.as_slice();
+        let first = match crate::kind(tokens, 0) {
+            Some(ParseToken::RepeatSpecial(' ', _)) => crate::kind(tokens, 1),
+            t => t,
+        };
+        match first {
+            Some(ParseToken::Number(_, _)) => Ok(Element::Ordered(
+                <Ordered as super::Element>::parse_with(iter, cx)?,
+            )),
+            Some(ParseToken::RepeatSpecial('-' | '*' | '+', 1)) => Ok(Element::Unordered(
+                <Unordered as super::Element>::parse_with(iter, cx)?,
+            )),
+            Some(ParseToken::RepeatSpecial(c, _)) => {
+                Err(crate::ParseErrorKind::UnexpectedChar(*c).into())
+            }
+            Some(t) => Err(crate::ParseErrorKind::UnexpectedString(t.to_string()).into()),
+            None => Err(crate::ParseErrorKind::EmptyDocument.into()),
+        }
+    }
+}
+
+/// The marker that starts a list item: a bullet, or a number followed by a
+/// `.` or `)` delimiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Marker {
+    Bullet(char),
+    Number(usize, char),
+}
+
+impl Marker {
+    pub(crate) fn ordered(&self) -> bool {
+        matches!(self, Self::Number(_, _))
+    }
+    /// Whether an item starting with `other` belongs to the same list as one
+    /// starting with `self`. Changing the bullet or delimiter starts a new
+    /// list.
+    pub(crate) fn continues(&self, other: &Marker) -> bool {
+        match (self, other) {
+            (Self::Bullet(a), Self::Bullet(b)) => a == b,
+            (Self::Number(_, a), Self::Number(_, b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Reads the list marker at the start of `line`, at any indentation. A
+/// thematic break such as `- - -` is not a list item.
+///
+/// Returns the marker, the number of tokens taken up by the indentation, the
+/// marker and the spaces after it, and the column the item's content starts
+/// at.
+pub(crate) fn marker(line: &[Token]) -> Option<(Marker, usize, usize)> {
+    if crate::block::thematic_break(line).is_some() {
+        return None;
+    }
+    let (indent, rest) = crate::indent(line);
+    let skip = line.len() - rest.len();
+    let (marker, len, width) = match (crate::kind(rest, 0)?, crate::kind(rest, 1)) {
+        (ParseToken::RepeatSpecial(c @ ('-' | '*' | '+'), 1), _) => (Marker::Bullet(*c), 1, 1),
+        (ParseToken::Number(n, Some(0)), _) => (Marker::Number(*n, '.'), 1, digits(*n) + 1),
+        (ParseToken::Number(n, None), Some(ParseToken::RepeatSpecial(')', 1))) => {
+            (Marker::Number(*n, ')'), 2, digits(*n) + 1)
+        }
+        _ => return None,
+    };
+    let column = indent + width;
+    match crate::kind(rest, len) {
+        None | Some(ParseToken::RepeatSpecial('\n' | '\r', _)) => {
+            Some((marker, skip + len, column + 1))
+        }
+        Some(ParseToken::RepeatSpecial(' ', n)) => {
+            // Five or more spaces start indented code inside the item, so
+            // only the first one belongs to the marker.
+            let n = if *n > 4 { 1 } else { *n };
+            Some((marker, skip + len + 1, column + n))
+        }
+        Some(ParseToken::RepeatSpecial('\t', _)) => Some((marker, skip + len + 1, column + 1)),
+        _ => None,
+    }
+}
+
+#[inline]
+fn digits(n: usize) -> usize {
+    n.to_string().len()
 }
 
+/// Whether `tokens` start with a list marker after up to three spaces of
+/// indentation.
+pub(crate) fn is_marker(tokens: &[Token]) -> bool {
+    crate::indent(tokens).0 <= 3 && marker(tokens).is_some()
+}
+
+/// Whether the list item `line` starts is a bullet or numbered 1.
+pub(crate) fn starts_at_one(line: &[Token]) -> bool {
+    !matches!(marker(line), Some((Marker::Number(n, _), ..)) if n != 1)
+}
+
+/// The lines of a list taken up by one of its items.
+#[derive(Debug, Clone, Copy)]
+struct Part {
+    /// The line the item starts on.
+    first: usize,
+    /// The last line of the item.
+    last: usize,
+    marker: Marker,
+    /// The tokens taken up by the marker on the first line.
+    skip: usize,
+    /// The column the item's content starts at.
+    column: usize,
+    /// Whether a blank line came before the item.
+    blank: bool,
+}
+
+/// Works out which of `lines` make up the list starting on the first one,
+/// item by item.
+fn parts(lines: &[crate::Line]) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let Some((first, skip, mut column)) = lines.first().and_then(|l| marker(l.tokens)) else {
+        return parts;
+    };
+    parts.push(Part {
+        first: 0,
+        last: 0,
+        marker: first,
+        skip,
+        column,
+        blank: false,
+    });
+    let mut blank = 0;
+    for (i, line) in lines.iter().enumerate().skip(1) {
+        if crate::is_blank(line.tokens) {
+            blank += 1;
+            continue;
+        }
+        let (n, _) = crate::indent(line.tokens);
+        match marker(line.tokens) {
+            Some((m, skip, col)) if n < column => {
+                if !first.continues(&m) {
+                    break;
+                }
+                column = col;
+                parts.push(Part {
+                    first: i,
+                    last: i,
+                    marker: m,
+                    skip,
+                    column,
+                    blank: blank > 0,
+                });
+            }
+            _ if n >= column => parts.last_mut().unwrap().last = i,
+            None if blank == 0 && !interrupts(line.tokens) => parts.last_mut().unwrap().last = i,
+            _ => break,
+        }
+        blank = 0;
+    }
+    parts
+}
+
+/// Whether `line` starts a block of its own rather than lazily continuing the
+/// paragraph of a list item.
+#[inline]
+pub(crate) fn interrupts(line: &[Token]) -> bool {
+    crate::document::interrupts_paragraph(line) || crate::text::setext_underline(line).is_some()
+}
+
+/// The number of lines taken up by the list starting on the first of `lines`.
+pub(crate) fn extent(lines: &[crate::Line]) -> usize {
+    parts(lines).last().map_or(0, |p| p.last + 1)
+}
+
+#[inline]
+fn text(tokens: &[Token]) -> String {
+    let s: String = tokens.iter().map(|t| t.to_string()).collect();
+    s.trim().to_string()
+}
+
+/// `tokens` with the first `skip` characters of their text left out, the
+/// token they end inside split.
+fn skip_chars(tokens: &[Token], skip: usize) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut left = skip;
+    for t in tokens {
+        let len = t.to_string().chars().count();
+        if left >= len {
+            left -= len;
+            continue;
+        }
+        let mut t = t.clone();
+        out.push(match crate::text::split_at(&mut t, left) {
+            Some(rest) => rest,
+            None => t,
+        });
+        left = 0;
+    }
+    out
+}
+
+/// The source text of the paragraph `p` an item starts with, found in the
+/// item's `tokens`, with the indentation of its lines left out. Spaces at the
+/// end of inner lines are kept, as they may make up a hard line break.
+fn paragraph_text(tokens: &[Token], p: &crate::text::Paragraph) -> String {
+    let range = p.span().range();
+    let text: String = tokens
+        .iter()
+        .filter(|t| range.start <= t.span.start().offset() && t.span.end().offset() <= range.end)
+        .map(|t| t.to_string())
+        .collect();
+    let lines: Vec<&str> = text.lines().map(str::trim_start).collect();
+    lines.join("\n").trim_end().to_string()
+}
+
+/// Parses the items of a list, along with the marker of its first item and
+/// whether it is tight.
+fn parse_items(
+    iter: &mut Iter<Token>,
+    ordered: bool,
+    cx: &mut crate::Context,
+) -> Result<(Vec<Item>, Vec<Marker>, bool), crate::ParseError> {
+    let tokens = iter.as_slice();
+    let lines = crate::lines(tokens);
+    let parts = parts(&lines);
+    match parts.first() {
+        Some(p) if p.marker.ordered() == ordered => {}
+        _ => {
+            let expected = if ordered {
+                "`1.` or `1)`"
+            } else {
+                "`-`, `*` or `+`"
+            };
+            let t = tokens
+                .iter()
+                .find(|t| !matches!(t.kind, ParseToken::RepeatSpecial(' ', _)));
+            return Err(crate::unexpected_or_end(t).with_expected(&[expected]));
+        }
+    }
+    let mut items = Vec::new();
+    let mut tight = true;
+    for part in parts.iter() {
+        tight &= !part.blank;
+        let line = lines[part.first].tokens;
+        let text = text(&line[part.skip..]);
+        let (checked, content) = task(&text);
+        let mut item = ItemBuilder::default();
+        let first: String = line[part.skip..].iter().map(|t| t.to_string()).collect();
+        let mut skip = 0;
+        if let Some(checked) = checked {
+            item = item.task(checked);
+            skip = first.len() - first.trim_start().len() + text.len() - content.len();
+        }
+        if ordered {
+            item = item.ordered();
+        }
+        let mut body = skip_chars(&line[part.skip..], skip);
+        if part.last > part.first {
+            let rest = &tokens[lines[part.first].end..lines[part.last].end];
+            body.extend(crate::dedent(rest, part.column));
+        }
+        let mut blocks = cx.nested(|cx| crate::document::parse_blocks(&body, cx))?;
+        tight &= !blocks
+            .windows(2)
+            .any(|w| w[1].span().start().line() > w[0].span().end().line() + 1);
+        let first = match blocks.first() {
+            Some(Block::Paragraph(p)) => Some((paragraph_text(&body, p), p.items().to_vec())),
+            _ => None,
+        };
+        if first.is_some() {
+            blocks.remove(0);
+        }
+        let (content, inlines) = first.unwrap_or_default();
+        items.push(Item {
+            content: content.into_boxed_str(),
+            items: inlines.into_boxed_slice(),
+            blocks: blocks.into_boxed_slice(),
+            span: crate::span_of(line).to(crate::span_of(lines[part.last].tokens)),
+            ..crate::Builder::build(item).unwrap()
+        });
+    }
+    let rest = &tokens[lines[parts.last().unwrap().last].end..];
+    let end = match crate::kind(rest, 0) {
+        Some(ParseToken::RepeatSpecial('\r', 1)) => 1,
+        _ => 0,
+    };
+    let end = match crate::kind(rest, end) {
+        Some(ParseToken::RepeatSpecial('\n', _)) => end + 1,
+        _ => end,
+    };
+    *iter = rest[end..].iter();
+    Ok((items, parts.iter().map(|p| p.marker).collect(), tight))
+}
+
+/// Splits a leading `[ ]`, `[x]` or `[X]` checkbox off the content of an item.
+fn task(s: &str) -> (Option<bool>, &str) {
+    let checked = match s.get(..3) {
+        Some("[ ]") => false,
+        Some("[x]" | "[X]") => true,
+        _ => return (None, s),
+    };
+    match s[3..].chars().next() {
+        None => (Some(checked), ""),
+        Some(' ') => (Some(checked), s[3..].trim_start()),
+        _ => (None, s),
+    }
+}
+
+#[allow(clippy::from_over_into)]
 impl Into<Element> for Unordered {
     fn into(self) -> Element {
         Element::Unordered(self)
     }
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<Element> for Ordered {
     fn into(self) -> Element {
         Element::Ordered(self)
     }
 }
 
+impl Item {
+    /// Renders the item after `marker`, lining continuation lines and nested
+    /// lists up with the start of its content.
+    fn render(&self, marker: &str, tight: bool) -> String {
+        let mut s = marker.to_string();
+        match self.checked {
+            Some(true) => s.push_str("[x]"),
+            Some(false) => s.push_str("[ ]"),
+            None => {}
+        }
+        if self.checked.is_some() && !self.content.is_empty() {
+            s.push(' ');
+        }
+        s.push_str(&pad(&self.content, marker.len()));
+        for (i, block) in self.blocks.iter().enumerate() {
+            // An item without text of its own starts with its first block.
+            // Paragraphs and indented code cannot interrupt what comes before
+            // them, so only a loose list can hold them after it.
+            let sep = match (i, tight, block) {
+                (0, _, _) if self.content.is_empty() && self.checked.is_none() => "",
+                (_, true, Block::Paragraph(_)) => "\n\n",
+                (_, true, Block::Code(c)) if c.fence() == crate::block::Fence::Indent => "\n\n",
+                (_, true, _) => "\n",
+                (_, false, _) => "\n\n",
+            };
+            // Nested lists are set off even from an item rendered on its own.
+            let width = match block {
+                Block::List(_) => marker.len().max(1),
+                _ => marker.len(),
+            };
+            s.push_str(&pad(&format!("{}{}", sep, block.to_string()), width));
+        }
+        s
+    }
+}
+
+/// Indents every line of `s` but the first by `width` spaces, leaving blank
+/// lines empty.
+fn pad(s: &str, width: usize) -> String {
+    s.split('\n')
+        .enumerate()
+        .map(|(i, l)| match i {
+            0 => l.to_string(),
+            _ if l.is_empty() => String::new(),
+            _ => format!("{}{}", " ".repeat(width), l),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Ordered {
     fn to_string(&self) -> String {
-        let mut s = String::new();
-        let mut i = 1;
-        for item in self.items.iter() {
-            s.push_str(format!("{}. {}\n", i, item.to_string()).as_str());
-            i += 1;
+        let sep = if self.tight { "\n" } else { "\n\n" };
+        let mut s = Vec::new();
+        for (i, item) in self.items.iter().enumerate() {
+            let n = if self.repeated {
+                self.start
+            } else {
+                self.start + i
+            };
+            let marker = format!("{}{} ", n, self.delimiter);
+            s.push(item.render(&marker, self.tight));
         }
-        s.pop();
-        return s;
+        s.join(sep)
     }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Element {
     fn to_string(&self) -> String {
         match self {
@@ -101,42 +667,47 @@ impl ToString for Element {
     }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Unordered {
     fn to_string(&self) -> String {
-        let mut s = String::new();
+        let sep = if self.tight { "\n" } else { "\n\n" };
+        let marker = format!("{} ", self.bullet);
+        let mut s = Vec::new();
         for item in self.items.iter() {
-            s.push_str(format!(" - {}", item.to_string()).as_str())
+            s.push(item.render(&marker, self.tight))
         }
-        return s;
+        s.join(sep)
     }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Item {
     fn to_string(&self) -> String {
-        let mut s = self.content.to_string();
-        let mut i = 1;
-        for item in self.indented.iter() {
-            s.push_str(format!("\n\t{}. {}", i, item).as_str());
-            i += 1;
-        }
-        return s;
+        self.render("", true)
     }
 }
 
 crate::impl_from_str!(Unordered);
 crate::impl_from_str!(Ordered);
+crate::impl_from_str!(Element);
 
 impl ItemBuilder {
     pub fn content(mut self, s: String) -> Self {
         self.content = s;
         self
     }
-    pub fn indent(mut self, l: Vec<Box<str>>) -> Self {
-        self.indented = l;
+    /// Replaces the blocks after the item's text with the lists `l`.
+    pub fn children(mut self, l: Vec<Element>) -> Self {
+        self.blocks = l.into_iter().map(Block::List).collect();
         self
     }
-    pub fn indent_push(mut self, s: String) -> Self {
-        self.indented.push(s.into_boxed_str());
+    pub fn child(mut self, l: Element) -> Self {
+        self.blocks.push(Block::List(l));
+        self
+    }
+    /// Adds a block after the item's text.
+    pub fn block(mut self, b: Block) -> Self {
+        self.blocks.push(b);
         self
     }
     pub fn ordered(mut self) -> Self {
@@ -147,15 +718,27 @@ impl ItemBuilder {
         self.ordered = false;
         self
     }
+    /// Makes the item a task with its checkbox in the given state.
+    pub fn task(mut self, checked: bool) -> Self {
+        self.checked = Some(checked);
+        self
+    }
 }
 
 impl crate::Builder for ItemBuilder {
     type Output = Item;
     fn build(self) -> Result<Self::Output, crate::Error> {
+        let items = crate::text::parse_inline(
+            &crate::tokenize(&self.content),
+            &mut crate::Context::default(),
+        );
         Ok(Self::Output {
             content: self.content.into_boxed_str(),
-            indented: self.indented.into_boxed_slice(),
+            items: items.into_boxed_slice(),
+            blocks: self.blocks.into_boxed_slice(),
             ordered: self.ordered,
+            checked: self.checked,
+            span: Span::default(),
         })
     }
 }
@@ -173,15 +756,77 @@ impl Builder {
         self.ordered = false;
         self
     }
+    /// Sets the number of the first item of an ordered list.
+    pub fn start(mut self, n: usize) -> Self {
+        self.start = n;
+        self
+    }
+    /// Sets the character after each number of an ordered list, `.` or `)`.
+    pub fn delimiter(mut self, c: char) -> Self {
+        self.delimiter = c;
+        self
+    }
+    /// Numbers every item of an ordered list like the first.
+    pub fn repeated(mut self) -> Self {
+        self.repeated = true;
+        self
+    }
+    /// Numbers the items of an ordered list counting up from the first.
+    pub fn sequential(mut self) -> Self {
+        self.repeated = false;
+        self
+    }
+    /// Sets the bullet of an unordered list, `-`, `*` or `+`.
+    pub fn bullet(mut self, c: char) -> Self {
+        self.bullet = c;
+        self
+    }
+    pub fn tight(mut self) -> Self {
+        self.tight = true;
+        self
+    }
+    pub fn loose(mut self) -> Self {
+        self.tight = false;
+        self
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            ordered: false,
+            start: 1,
+            delimiter: '.',
+            repeated: false,
+            bullet: '-',
+            tight: true,
+        }
+    }
 }
 
 impl crate::Builder for Builder {
     type Output = Element;
     fn build(self) -> Result<Self::Output, crate::Error> {
+        if !matches!(self.delimiter, '.' | ')') || !matches!(self.bullet, '-' | '*' | '+') {
+            return Err(crate::Error::InvalidData);
+        }
         let items = self.items.into_boxed_slice();
-        return Ok(match self.ordered {
-            true => Self::Output::Ordered(Ordered { items }),
-            false => Self::Output::Unordered(Unordered { items }),
-        });
+        Ok(match self.ordered {
+            true => Self::Output::Ordered(Ordered {
+                items,
+                start: self.start,
+                delimiter: self.delimiter,
+                repeated: self.repeated,
+                tight: self.tight,
+                span: Span::default(),
+            }),
+            false => Self::Output::Unordered(Unordered {
+                items,
+                bullet: self.bullet,
+                tight: self.tight,
+                span: Span::default(),
+            }),
+        })
     }
 }
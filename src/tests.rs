@@ -1,11 +1,27 @@
-use super::Builder;
-use super::{block, list, text};
+use super::{block, document, list, text};
+use super::{Builder, Span};
 use std::str::FromStr;
 
 #[test]
 fn parse_list() {
     assert_eq!(
-        Ok::<list::Element, crate::ParseError>(list::Builder::default().build().unwrap()),
+        Ok::<list::Element, crate::ParseError>(
+            list::Builder::default()
+                .push(
+                    list::ItemBuilder::default()
+                        .content("First item".to_string())
+                        .build()
+                        .unwrap()
+                )
+                .push(
+                    list::ItemBuilder::default()
+                        .content("Second item".to_string())
+                        .build()
+                        .unwrap()
+                )
+                .build()
+                .unwrap()
+        ),
         Ok(list::Element::Unordered(
             list::Unordered::from_str(" - First item\n - Second item").expect("Error")
         ))
@@ -17,14 +33,18 @@ fn parse_code() {
     assert_eq!(
         Ok(block::Code {
             content: "code".to_string().into_boxed_str(),
-            kind: block::CodeKind::None(2)
+            kind: block::CodeKind::None,
+            fence: block::Fence::Backticks(2),
+            span: Span::default(),
         }),
         block::Code::from_str("``code``")
     );
     assert_eq!(
         Ok(block::Code {
             content: "a type of code".to_string().into_boxed_str(),
-            kind: block::CodeKind::Unknown("code".to_string().into_boxed_str(), 3),
+            kind: block::CodeKind::Unknown("code".to_string().into_boxed_str()),
+            fence: block::Fence::Backticks(3),
+            span: Span::default(),
         }),
         block::Code::from_str("```code\na type of code\n```")
     );
@@ -118,6 +138,35 @@ fn parse_reference() {
             .unwrap()),
         text::Reference::from_str("[1]: <https://www.example.com/>")
     );
+    let reference = |title: &str| {
+        text::ReferenceBuilder::default()
+            .name("a")
+            .href(url::Url::parse("https://x.com/").unwrap())
+            .title(title)
+            .build()
+            .unwrap()
+    };
+    for src in [
+        "[a]: https://x.com",
+        "[a]: https://x.com  ",
+        "[a]:<https://x.com>",
+    ] {
+        assert_eq!(Ok(reference("")), text::Reference::from_str(src));
+    }
+    for src in [
+        "[a]: https://x.com \"Title\"",
+        "[a]: https://x.com 'Title'",
+        "[a]: https://x.com (Title)",
+        "[a]: <https://x.com> \"Title\" ",
+    ] {
+        let parsed = text::Reference::from_str(src).unwrap();
+        assert_eq!(reference("Title"), parsed);
+        assert_eq!("Title", parsed.title());
+    }
+    assert!(text::Reference::from_str("[a]: https://x.com \"Title").is_err());
+    assert!(text::Reference::from_str("[a]: https://x.com \"Title\" b").is_err());
+    let doc = document::Document::from_str("[a]\n\n[a]: https://x.com 'Title'").unwrap();
+    assert_eq!(Some("Title"), doc.reference("a").map(|r| r.title()));
 }
 
 #[test]
@@ -186,27 +235,74 @@ fn parse_heading() {
             .unwrap()),
         text::Heading::from_str("Heading 2\n----")
     );
+    for (src, level) in [
+        ("#5 issue\n---", text::HeadingLvl::Level2),
+        ("#hashtag\n===", text::HeadingLvl::Level1),
+    ] {
+        let doc = document::Document::from_str(src).unwrap();
+        match doc.blocks() {
+            [document::Block::Heading(h)] => assert_eq!(level, h.level()),
+            b => panic!("expected a heading, got {:?}", b),
+        }
+        assert_eq!(doc, document::Document::from_str(&doc.to_string()).unwrap());
+    }
+    for (src, content) in [
+        ("# Title #", "Title"),
+        ("## Heading ##", "Heading"),
+        ("# Title\t###", "Title"),
+        ("# a #b", "a #b"),
+        ("# Title \\#", "Title #"),
+    ] {
+        let heading = text::Heading::from_str(src).unwrap();
+        assert_eq!(
+            &[text::Item::Def(content.into(), Span::default())],
+            heading.items()
+        );
+        assert_eq!(Ok(heading.clone()), heading.to_string().parse());
+    }
+    for (src, out) in [
+        ("a\nb\n===", "a\nb\n==="),
+        ("a\n\\# b\n--", "a\n\\# b\n---"),
+    ] {
+        let heading = text::Heading::from_str(src).unwrap();
+        assert_eq!(out, heading.to_string());
+        assert_eq!(Ok(heading.clone()), heading.to_string().parse());
+    }
+    let joined = text::HeadingBuilder::default()
+        .level(text::HeadingLvl::Level3)
+        .push(text::Item::Def("a".into(), Span::default()))
+        .push(text::Item::Break(Span::default()))
+        .push(text::Item::Def("b".into(), Span::default()))
+        .build()
+        .unwrap();
+    assert_eq!("### a b", joined.to_string());
 }
 
 #[test]
 fn parse_text() {
     assert_eq!(
-        Ok(text::Item::Bold("bold text".to_string().into_boxed_str())),
+        text::ItemBuilder::default()
+            .content("bold text".to_string())
+            .bold()
+            .build()
+            .map_err(crate::ParseError::from),
         text::Item::from_str("**bold text**")
     );
     assert_eq!(
-        Err(crate::ParseError::UnexpectedChar('?')),
+        Err(crate::ParseErrorKind::UnexpectedChar('?').into()),
         text::Item::from_str("**bold text**?")
     );
     assert_eq!(
         Ok(text::Item::Italic(
-            "italic text".to_string().into_boxed_str()
+            Box::new([text::Item::Def("italic text".into(), Span::default())]),
+            Span::default()
         )),
         text::Item::from_str("*italic text*")
     );
     assert_eq!(
         Ok(text::Item::BoldItalic(
-            "bold italic text".to_string().into_boxed_str()
+            Box::new([text::Item::Def("bold italic text".into(), Span::default())]),
+            Span::default()
         )),
         text::Item::from_str("***bold italic text***")
     );
@@ -231,3 +327,1411 @@ fn parse_text() {
         text::Item::from_str("![link](https://example.com)")
     );
 }
+
+#[test]
+fn parse_document() {
+    let src = "# Title\n\nSome text, with punctuation.\nSecond line.\n\n```rust\nfn main() {}\n\nmain();\n```\n\n- one\n- two\n\n1. first\n2. second\n\n> quoted\n> text\n\n[1]: <https://example.com/>\n\nSub\n---";
+    let doc = document::Document::from_str(src).expect("document");
+    assert_eq!(
+        vec![
+            document::Block::Heading(
+                text::HeadingBuilder::default()
+                    .content("Title".to_string())
+                    .build()
+                    .unwrap()
+            ),
+            text::ParagraphBuilder::default()
+                .content("Some text, with punctuation.".to_string())
+                .push(text::ItemBuilder::Break.build().unwrap())
+                .content("Second line.".to_string())
+                .build()
+                .unwrap()
+                .into(),
+            block::CodeBuilder::default()
+                .content("fn main() {}\n\nmain();")
+                .kind(block::CodeKind::Rust)
+                .build()
+                .unwrap()
+                .into(),
+            list::Element::from_str("- one\n- two").unwrap().into(),
+            list::Element::from_str("1. first\n2. second")
+                .unwrap()
+                .into(),
+            block::QuoteBuilder::default()
+                .push(
+                    text::ParagraphBuilder::default()
+                        .content("quoted".to_string())
+                        .push(text::ItemBuilder::Break.build().unwrap())
+                        .content("text".to_string())
+                        .build()
+                        .unwrap()
+                        .into()
+                )
+                .build()
+                .unwrap()
+                .into(),
+            text::Reference::from_str("[1]: <https://example.com/>")
+                .unwrap()
+                .into(),
+            text::HeadingBuilder::default()
+                .content("Sub".to_string())
+                .level(text::HeadingLvl::Level2)
+                .build()
+                .unwrap()
+                .into(),
+        ],
+        doc.blocks().to_vec()
+    );
+    assert_eq!(
+        Ok(document::Document::default()),
+        document::Document::from_str("\n\n")
+    );
+}
+
+#[test]
+fn parse_with_parser() {
+    use crate::Parser;
+    let parser = document::Parser::default();
+    let src = "- one\n\t- nested";
+    let expected = document::Document::from_str("- one\n    - nested").unwrap();
+    assert_eq!(Ok(expected.clone()), parser.parse(src));
+    assert_eq!(Ok(expected.clone()), parser.parse(src.to_string()));
+    assert_eq!(Ok(expected), parser.parse(&mut src.as_bytes()));
+    assert_eq!(
+        Ok(document::Document::from_str("- one\n  - nested").unwrap()),
+        document::Parser::default().tab_width(2).parse(src)
+    );
+    let span = parser.parse(src).unwrap().blocks()[0].span();
+    assert_eq!(
+        (2, 10, 15),
+        (span.end().line(), span.end().column(), span.range().end)
+    );
+    let code = parser.parse("\tlet x;").unwrap().blocks()[0].span();
+    assert_eq!(
+        (1, 8, 0..7),
+        (code.column(), code.end().column(), code.range())
+    );
+    assert_eq!(
+        Err(crate::ParseErrorKind::Io(std::io::ErrorKind::InvalidData.into()).into()),
+        parser.parse(&mut &[0xffu8, 0xfe][..])
+    );
+    let err = parser.parse(&mut &[0xffu8, 0xfe][..]).unwrap_err();
+    let source = std::error::Error::source(&err).unwrap();
+    assert_eq!(format!("I/O error: {}", source), err.to_string());
+}
+
+#[test]
+fn spans() {
+    let src = "# Title\n\nSome *text*.\n\n```\ncode\n```\n\n- one\n- two\n  - three\n";
+    let doc = document::Document::from_str(src).unwrap();
+    let ranges: Vec<_> = doc.iter().map(|b| b.span().range()).collect();
+    assert_eq!(vec![0..7, 9..21, 23..35, 37..58], ranges);
+    let code = doc.blocks()[2].span();
+    assert_eq!((5, 1), (code.line(), code.column()));
+    assert_eq!((7, 4), (code.end().line(), code.end().column()));
+    if let document::Block::List(l) = &doc.blocks()[3] {
+        let items: Vec<_> = l.items().iter().map(|i| i.span().range()).collect();
+        assert_eq!(vec![37..42, 43..58], items);
+        let nested = &l.items()[1].children()[0].items()[0];
+        assert_eq!(53..58, nested.items()[0].span().range());
+    } else {
+        panic!("expected a list");
+    }
+    let item = text::Item::from_str("[link](https://example.com)").unwrap();
+    assert_eq!(0..27, item.span().range());
+    let list = list::Element::from_str("1. [ ] a *b*\n   c").unwrap();
+    let spans: Vec<_> = list.items()[0]
+        .items()
+        .iter()
+        .map(|i| i.span().range())
+        .collect();
+    assert_eq!(vec![7..9, 9..12, 12..13, 16..17], spans);
+    let moved = document::Document::from_str(&format!("\n\n{}", src)).unwrap();
+    assert_eq!(doc, moved);
+    assert_ne!(doc.blocks()[0].span(), moved.blocks()[0].span());
+    assert!(doc.blocks()[0].span() < moved.blocks()[0].span());
+    let tokens = super::tokenize("a\n**b**");
+    let spans: Vec<_> = tokens
+        .iter()
+        .map(|t| (t.span.line(), t.span.column(), t.span.range()))
+        .collect();
+    assert_eq!(
+        vec![
+            (1, 1, 0..1),
+            (1, 2, 1..2),
+            (2, 1, 2..4),
+            (2, 3, 4..5),
+            (2, 4, 5..7)
+        ],
+        spans
+    );
+}
+
+#[test]
+fn diagnostics() {
+    let err = text::Item::from_str("**bold text**?").unwrap_err();
+    assert_eq!(Some((1, 14)), err.span().map(|s| (s.line(), s.column())));
+    assert_eq!(
+        "unexpected character `?`\n --> 1:14\n  |\n1 | **bold text**?\n  |              ^ expected end of input",
+        err.to_string()
+    );
+    let err = text::Item::from_str("[link(https://example.com)").unwrap_err();
+    assert_eq!(&crate::ParseErrorKind::UnexpectedEnd, err.kind());
+    assert_eq!(
+        vec!["`]`"],
+        err.expected().iter().map(|e| &**e).collect::<Vec<_>>()
+    );
+    let err = text::Item::from_str("[link](https://example.com").unwrap_err();
+    assert_eq!(
+        "unexpected end of input\n --> 1:27\n  |\n1 | [link](https://example.com\n  |                           ^ expected `)`",
+        err.to_string()
+    );
+    let err: &dyn std::error::Error = &err;
+    assert!(err.source().is_none());
+}
+
+#[test]
+fn parse_lenient() {
+    let src = format!("Intro\n\n{}a\n\nOutro", "> ".repeat(crate::MAX_DEPTH + 1));
+    assert!(document::Document::from_str(&src).is_err());
+    let (doc, warnings) = document::Parser::default().parse_lenient(&src);
+    let mut literal = text::Paragraph::from_str("> a").unwrap().into();
+    for _ in 0..crate::MAX_DEPTH {
+        literal = block::QuoteBuilder::default()
+            .push(literal)
+            .build()
+            .unwrap()
+            .into();
+    }
+    let expected = document::Builder::default()
+        .push(text::Paragraph::from_str("Intro").unwrap().into())
+        .push(literal)
+        .push(text::Paragraph::from_str("Outro").unwrap().into())
+        .build()
+        .unwrap();
+    assert_eq!(expected, doc);
+    assert_eq!(
+        vec![crate::ParseError::from(crate::ParseErrorKind::TooDeep)],
+        warnings
+    );
+    assert_eq!(Some(3), warnings[0].span().map(|s| s.line()));
+    let (_, warnings) = document::Parser::default().parse_lenient("# Title\n\nText");
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn parse_list_items() {
+    let list = list::Element::from_str("- one\n  more\nlazy\n\n  para two\n- two\n").unwrap();
+    let contents: Vec<_> = list.items().iter().map(|i| i.to_string()).collect();
+    assert_eq!(vec!["one\nmore\nlazy\n\npara two", "two"], contents);
+    assert_eq!(
+        list,
+        list::Element::from_str(list.to_string().as_str()).unwrap()
+    );
+    let ordered = list::Element::from_str("1) a\n2) b").unwrap();
+    assert!(matches!(ordered, list::Element::Ordered(_)));
+    assert_eq!(2, ordered.items().len());
+    let doc = document::Document::from_str("1) a\n2) b\n3. c\n\n* d\n+ e").unwrap();
+    let lens: Vec<_> = doc
+        .iter()
+        .map(|b| match b {
+            document::Block::List(l) => l.items().len(),
+            _ => 0,
+        })
+        .collect();
+    assert_eq!(vec![2, 1, 1, 1], lens);
+    assert!(list::Element::from_str("- a\n\nafter").is_err());
+    let code = |item: &list::Item| match item.blocks() {
+        [document::Block::Code(c)] => c.clone(),
+        b => panic!("expected code, got {:?}", b),
+    };
+    let fenced = list::Element::from_str("1. step\n\n   ```sh\n   make\n   ```").unwrap();
+    let c = code(&fenced.items()[0]);
+    assert_eq!(
+        ("make", block::Fence::Backticks(3)),
+        (c.content(), c.fence())
+    );
+    assert_eq!("step", fenced.items()[0].content());
+    let indented = list::Element::from_str("- a\n\n      code").unwrap();
+    assert_eq!("code", code(&indented.items()[0]).content());
+    assert_eq!("- a\n\n      code", indented.to_string());
+    let first = list::Element::from_str("- ```\n  x\n  ```").unwrap();
+    assert_eq!("x", code(&first.items()[0]).content());
+    assert_eq!("- ```\n  x\n  ```", first.to_string());
+    let paragraphs = list::Element::from_str("- a\n\n  b").unwrap();
+    assert_eq!(1, paragraphs.items()[0].items().len());
+    assert!(matches!(
+        paragraphs.items()[0].blocks(),
+        [document::Block::Paragraph(_)]
+    ));
+    assert!(!paragraphs.is_tight());
+    for src in ["-\tx", "1.\tx"] {
+        let doc = document::Document::from_str(src).unwrap();
+        match doc.blocks() {
+            [document::Block::List(l)] => assert_eq!("x", l.items()[0].content()),
+            b => panic!("expected a list, got {:?}", b),
+        }
+    }
+    let broken = list::Element::from_str("- line one  \n  line two").unwrap();
+    assert!(matches!(
+        broken.items()[0].items(),
+        [_, text::Item::HardBreak(text::HardBreak::Spaces, _), _]
+    ));
+    assert_eq!("- line one  \n  line two", broken.to_string());
+}
+
+#[test]
+fn nested_lists() {
+    let src = "- one\n  - a\n    1. deep\n  - b\n- two";
+    let deep = list::Builder::default()
+        .ordered()
+        .push(
+            list::ItemBuilder::default()
+                .content("deep".to_string())
+                .ordered()
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+    let inner = list::Builder::default()
+        .push(
+            list::ItemBuilder::default()
+                .content("a".to_string())
+                .child(deep)
+                .build()
+                .unwrap(),
+        )
+        .push(
+            list::ItemBuilder::default()
+                .content("b".to_string())
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+    let expected = list::Builder::default()
+        .push(
+            list::ItemBuilder::default()
+                .content("one".to_string())
+                .child(inner)
+                .build()
+                .unwrap(),
+        )
+        .push(
+            list::ItemBuilder::default()
+                .content("two".to_string())
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+    let list = list::Element::from_str(src).unwrap();
+    assert_eq!(expected, list);
+    assert_eq!(0..29, list.items()[0].span().range());
+    assert_eq!(
+        list,
+        list::Element::from_str(list.to_string().as_str()).unwrap()
+    );
+}
+
+#[test]
+fn list_attributes() {
+    for src in [
+        "5) five\n6) six",
+        "1. a\n1. b\n1. c",
+        "* a\n* b",
+        "+ a\n\n+ b\n\n  more",
+        "- a\n\n  - b",
+    ] {
+        assert_eq!(src, list::Element::from_str(src).unwrap().to_string());
+    }
+    if let list::Element::Ordered(l) = list::Element::from_str("5) five\n6) six").unwrap() {
+        assert_eq!((5, ')', true), (l.start(), l.delimiter(), l.is_tight()));
+        assert!(!l.is_repeated());
+    } else {
+        panic!("expected an ordered list");
+    }
+    match list::Element::from_str("1. a\n1. b").unwrap() {
+        list::Element::Ordered(l) => assert!(l.is_repeated()),
+        _ => panic!("expected an ordered list"),
+    }
+    match list::Element::from_str("1. a\n2. b\n2. c").unwrap() {
+        list::Element::Ordered(l) => assert!(!l.is_repeated()),
+        _ => panic!("expected an ordered list"),
+    }
+    assert!(!list::Element::from_str("- a\n\n- b").unwrap().is_tight());
+    let item = list::ItemBuilder::default()
+        .content("x".to_string())
+        .build()
+        .unwrap();
+    let built = list::Builder::default()
+        .ordered()
+        .start(3)
+        .delimiter(')')
+        .loose()
+        .push(item.clone())
+        .push(item.clone())
+        .build()
+        .unwrap();
+    assert_eq!("3) x\n\n4) x", built.to_string());
+    let built = list::Builder::default()
+        .ordered()
+        .start(3)
+        .repeated()
+        .push(item.clone())
+        .push(item.clone())
+        .build()
+        .unwrap();
+    assert_eq!("3. x\n3. x", built.to_string());
+    let built = list::Builder::default().bullet('*').push(item).build();
+    assert_eq!("* x", built.unwrap().to_string());
+    assert_eq!(
+        Err(crate::Error::InvalidData),
+        list::Builder::default().bullet('x').build()
+    );
+    let kinds = |src: &str| {
+        let doc = document::Document::from_str(src).unwrap();
+        doc.blocks()
+            .iter()
+            .map(|b| matches!(b, document::Block::List(_)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec![false], kinds("The year\n2024. was long"));
+    assert_eq!(vec![false, true], kinds("Steps\n1. one\n2. two"));
+    assert_eq!(vec![false, true], kinds("Items\n- one"));
+}
+
+#[test]
+fn task_lists() {
+    let src =
+        "# Todo\n\n- [x] write\n- [ ] test\n  - [X] nested\n  - [ ]\n- plain\n- [y] not a task";
+    let mut doc = document::Document::from_str(src).unwrap();
+    assert_eq!((2, 4), doc.progress());
+    let unchecked: Vec<_> = doc.unchecked().map(|i| i.content()).collect();
+    assert_eq!(vec!["test", ""], unchecked);
+    assert_eq!(
+        "# Todo\n\n- [x] write\n- [ ] test\n  - [x] nested\n  - [ ]\n- plain\n- [y] not a task",
+        doc.to_string()
+    );
+    if let document::Block::List(l) = &mut doc.blocks_mut()[1] {
+        assert_eq!(Some(true), l.items_mut()[1].toggle());
+        assert_eq!(None, l.items_mut()[2].toggle());
+    }
+    assert_eq!((3, 4), doc.progress());
+    let item = list::ItemBuilder::default()
+        .content("done".to_string())
+        .task(true)
+        .build()
+        .unwrap();
+    assert_eq!(Some(true), item.checked());
+    assert_eq!("[x] done", item.to_string());
+}
+
+#[test]
+fn parse_paragraph() {
+    let src = "Plain *soft* and **strong** text\nwith `code`, [a link](https://example.com/)\nand ![an image](https://example.com/a.png).";
+    let p = text::Paragraph::from_str(src).unwrap();
+    let link = text::LinkBuilder::default()
+        .name("a link".to_string())
+        .href(url::Url::parse("https://example.com/").unwrap())
+        .build()
+        .unwrap();
+    let image = text::LinkBuilder::default()
+        .name("an image".to_string())
+        .href(url::Url::parse("https://example.com/a.png").unwrap())
+        .make_img()
+        .build()
+        .unwrap();
+    let item = |b: text::ItemBuilder| b.build().unwrap();
+    let def = |s: &str| item(text::ItemBuilder::Def(s.to_string()));
+    let expected = text::ParagraphBuilder::default()
+        .content("Plain ".to_string())
+        .push(item(text::ItemBuilder::Def("soft".to_string()).italic()))
+        .push(def(" and "))
+        .push(item(text::ItemBuilder::Def("strong".to_string()).bold()))
+        .push(def(" text"))
+        .push(item(text::ItemBuilder::Break))
+        .push(def("with "))
+        .push(item(text::ItemBuilder::Code("code".to_string())))
+        .push(def(", "))
+        .push(link.into())
+        .push(item(text::ItemBuilder::Break))
+        .push(def("and "))
+        .push(image.into())
+        .push(def("."))
+        .build()
+        .unwrap();
+    assert_eq!(expected, p);
+    assert_eq!(src, p.to_string());
+    assert_eq!(6..12, p.items()[1].span().range());
+    let p = text::Paragraph::from_str("a * b [c] d").unwrap();
+    assert_eq!("a * b [c] d", p.to_string());
+    let (_, warnings) = document::Parser::default().parse_lenient("see [c](a b c) d");
+    assert_eq!(1, warnings.len());
+    assert_eq!(
+        &crate::ParseErrorKind::UnexpectedChar('b'),
+        warnings[0].kind()
+    );
+    assert_eq!(Some(10..11), warnings[0].span().map(|s| s.range()));
+    for src in [
+        "see [c] d".to_string(),
+        "Use array[0] and see [note] or f(x) [sic].".to_string(),
+        "![".repeat(20_000),
+    ] {
+        let (_, warnings) = document::Parser::default().parse_lenient(&src);
+        assert!(warnings.is_empty());
+    }
+}
+
+#[test]
+fn nested_inlines() {
+    let src = "**bold with [a link](https://example.com/)** and *italic with `code`*";
+    let p = text::Paragraph::from_str(src).unwrap();
+    let item = |b: text::ItemBuilder| b.build().unwrap();
+    let def = |s: &str| item(text::ItemBuilder::Def(s.to_string()));
+    let link = text::LinkBuilder::default()
+        .name("a link".to_string())
+        .href(url::Url::parse("https://example.com/").unwrap())
+        .build()
+        .unwrap();
+    let expected = text::ParagraphBuilder::default()
+        .push(item(
+            text::ItemBuilder::Bold(vec![def("bold with ")]).push(link.into()),
+        ))
+        .push(def(" and "))
+        .push(item(
+            text::ItemBuilder::Def("italic with ".to_string())
+                .italic()
+                .push(item(text::ItemBuilder::Code("code".to_string()))),
+        ))
+        .build()
+        .unwrap();
+    assert_eq!(expected, p);
+    assert_eq!(src, p.to_string());
+    assert_eq!("bold with a link", p.items()[0].text());
+    let p = text::Paragraph::from_str("[**strong** link](https://example.com/)").unwrap();
+    if let text::Item::Link(l) = &p.items()[0] {
+        assert_eq!("strong link", l.name());
+        assert_eq!(
+            item(text::ItemBuilder::Def("strong".to_string()).bold()),
+            l.items()[0]
+        );
+    } else {
+        panic!("expected a link");
+    }
+    let p = text::Paragraph::from_str("*a `*` b*").unwrap();
+    assert_eq!(1, p.items().len());
+    assert_eq!(3, p.items()[0].children().len());
+    assert_eq!(
+        text::ItemBuilder::Def("x".to_string()),
+        text::ItemBuilder::Def("x".to_string())
+            .bold()
+            .italic()
+            .bold()
+            .italic()
+    );
+    for (src, name) in [
+        ("[a ](http://x.com/)", "a "),
+        ("[ a  b ](http://x.com/)", " a  b "),
+        ("[ ][x]\n\n[x]: http://x.com/", " "),
+    ] {
+        let doc = document::Document::from_str(src).unwrap();
+        match &doc.blocks()[0] {
+            document::Block::Paragraph(p) => match &p.items()[0] {
+                text::Item::Link(l) => assert_eq!(name, l.name()),
+                i => panic!("expected a link, got {:?}", i),
+            },
+            b => panic!("expected a paragraph, got {:?}", b),
+        }
+        assert!(doc.to_string().starts_with(src.lines().next().unwrap()));
+        assert_eq!(doc, document::Document::from_str(&doc.to_string()).unwrap());
+    }
+}
+
+#[test]
+fn delimiter_runs() {
+    let parse = |s: &str| text::Paragraph::from_str(s).unwrap();
+    let def = |s: &str| text::ItemBuilder::Def(s.to_string());
+    let item = |b: text::ItemBuilder| b.build().unwrap();
+    assert_eq!(parse("*foo bar*"), parse("_foo bar_"));
+    assert_eq!(parse("**foo bar**"), parse("__foo bar__"));
+    assert_eq!("**init**", parse("__init__").to_string());
+    for s in [
+        "snake_case_name",
+        "foo__bar__",
+        "_ foo bar_",
+        "a * foo bar*",
+        "_foo*",
+    ] {
+        assert_eq!(&[item(def(s))], parse(s).items());
+    }
+    assert_eq!(
+        &[item(def("foo_bar_baz").italic())],
+        parse("_foo_bar_baz_").items()
+    );
+    assert_eq!(
+        &[item(def("foo**bar").italic())],
+        parse("*foo**bar*").items()
+    );
+    assert_eq!(
+        &[
+            item(def("foo")),
+            item(def("bar").bold().italic()),
+            item(def("baz"))
+        ],
+        parse("foo***bar***baz").items()
+    );
+    assert_eq!(
+        &[item(
+            def("foo")
+                .italic()
+                .push(item(def("bar").bold()))
+                .push(item(def("baz")))
+        )],
+        parse("*foo**bar**baz*").items()
+    );
+    assert_eq!(
+        &[item(def("*")), item(def("foo").bold())],
+        parse("***foo**").items()
+    );
+    let p = parse("*foo _bar* baz_");
+    assert_eq!(
+        &[item(def("foo _bar").italic()), item(def(" baz_"))],
+        p.items()
+    );
+    assert_eq!(0..10, p.items()[0].span().range());
+    assert_eq!(
+        text::Item::from_str("__bold__"),
+        text::Item::from_str("**bold**")
+    );
+}
+
+#[test]
+fn code_spans() {
+    let code = |s: &str| text::ItemBuilder::Code(s.to_string()).build().unwrap();
+    let parse = |s: &str| text::Paragraph::from_str(s).unwrap();
+    for (src, content) in [
+        ("`foo`", "foo"),
+        ("`` foo ` bar ``", "foo ` bar"),
+        ("` `` `", "``"),
+        ("`  ``  `", " `` "),
+        ("` a`", " a"),
+        ("`  `", "  "),
+        ("``foo`bar``", "foo`bar"),
+        ("`foo   bar \n  baz`", "foo   bar  baz"),
+    ] {
+        let p = parse(src);
+        assert_eq!(&[code(content)], p.items());
+        assert_eq!(p, parse(&p.to_string()));
+    }
+    assert_eq!("``foo ` bar``", code("foo ` bar").to_string());
+    assert_eq!("`  ``  `", code(" `` ").to_string());
+    assert_eq!(
+        &[text::ItemBuilder::Def("```foo``".to_string())
+            .build()
+            .unwrap()],
+        parse("```foo``").items()
+    );
+    let h = text::Heading::from_str("## Use `cargo test` #").unwrap();
+    assert_eq!(
+        text::HeadingBuilder::default()
+            .content("Use ".to_string())
+            .push(code("cargo test"))
+            .level(text::HeadingLvl::Level2)
+            .build()
+            .unwrap(),
+        h
+    );
+    assert_eq!("## Use `cargo test`", h.to_string());
+    let l = list::Element::from_str("- run `make` first").unwrap();
+    assert_eq!(code("make"), l.items()[0].items()[1]);
+}
+
+#[test]
+fn inline_extensions() {
+    let src = "~~deleted~~, ==marked==, x^2^ and H~2~O";
+    let p = text::Paragraph::from_str(src).unwrap();
+    let def = |s: &str| text::ItemBuilder::Def(s.to_string());
+    let item = |b: text::ItemBuilder| b.build().unwrap();
+    let expected = text::ParagraphBuilder::default()
+        .push(item(def("deleted").strikethrough()))
+        .content(", ".to_string())
+        .push(item(def("marked").highlight()))
+        .content(", x".to_string())
+        .push(item(def("2").superscript()))
+        .content(" and H".to_string())
+        .push(item(def("2").subscript()))
+        .content("O".to_string())
+        .build()
+        .unwrap();
+    assert_eq!(expected, p);
+    assert_eq!(src, p.to_string());
+    let p = text::Paragraph::from_str("~~a **b** c~~ and 2^10 or ~a b~").unwrap();
+    assert_eq!(3, p.items()[0].children().len());
+    assert_eq!(" and 2^10 or ~a b~", p.items()[1].text());
+    let parse = |e: text::Extensions, s: &str| {
+        let doc = crate::Parser::parse(&document::Parser::default().extensions(e), s).unwrap();
+        match &doc.blocks()[0] {
+            document::Block::Paragraph(p) => p.items().to_vec(),
+            _ => panic!("expected a paragraph"),
+        }
+    };
+    assert_eq!(
+        vec![item(def("gone").strikethrough())],
+        parse(text::Extensions::gfm(), "~gone~")
+    );
+    assert_eq!(
+        vec![item(def("==marked=="))],
+        parse(text::Extensions::gfm(), "==marked==")
+    );
+    assert_eq!(
+        vec![item(def("~~a~~ ^b^"))],
+        parse(text::Extensions::commonmark(), "~~a~~ ^b^")
+    );
+    assert_eq!(
+        vec![item(def("a").highlight())],
+        parse(text::Extensions::commonmark().highlight(true), "==a==")
+    );
+    let list = |e: text::Extensions| {
+        let doc =
+            crate::Parser::parse(&document::Parser::default().extensions(e), "- ~~a~~").unwrap();
+        match &doc.blocks()[0] {
+            document::Block::List(l) => l.items()[0].items().to_vec(),
+            _ => panic!("expected a list"),
+        }
+    };
+    assert_eq!(
+        vec![item(def("~~a~~"))],
+        list(text::Extensions::commonmark())
+    );
+    assert_eq!(
+        vec![item(def("a").strikethrough())],
+        list(text::Extensions::gfm())
+    );
+}
+
+#[test]
+fn escapes_and_entities() {
+    let parse = |s: &str| text::Paragraph::from_str(s).unwrap();
+    let def = |s: &str| text::ItemBuilder::Def(s.to_string()).build().unwrap();
+    let p = parse("\\*not emphasis\\* &amp; &copy; &#35; &#x27; &nosuch;");
+    assert_eq!(&[def("*not emphasis* & © # ' &nosuch;")], p.items());
+    assert_eq!("\\*not emphasis\\* & © # ' &nosuch;", p.to_string());
+    assert_eq!(&[def("*foo*")], parse("&#42;foo&#42;").items());
+    let italic = text::ItemBuilder::Def("a".to_string()).italic();
+    assert_eq!(
+        &[def("\\"), italic.build().unwrap()],
+        parse("\\\\*a*").items()
+    );
+    let p = parse("\\`not code and `\\*code`");
+    assert_eq!(
+        text::ItemBuilder::Code("\\*code".to_string())
+            .build()
+            .unwrap(),
+        p.items()[1]
+    );
+    let p = parse("[a\\]b](https://example.com/) \\[c](d)");
+    if let text::Item::Link(l) = &p.items()[0] {
+        assert_eq!("a]b", l.name());
+    } else {
+        panic!("expected a link");
+    }
+    for src in [
+        "\\# not a heading",
+        "1\\. not a list",
+        "\\- not a list",
+        "&amp;amp; and \\&copy;",
+        "C:\\\\path and \\\\*x*",
+        "[a\\]b](https://example.com/) \\[c\\](d)",
+        "snake_case and \\_x\\_",
+    ] {
+        let p = parse(src);
+        assert_eq!(p, parse(&p.to_string()), "{}", src);
+    }
+    assert_eq!(
+        "snake_case and a [b] c",
+        parse("snake_case and a [b] c").to_string()
+    );
+}
+
+#[test]
+fn autolinks() {
+    let parse = |s: &str| text::Paragraph::from_str(s).unwrap();
+    let auto = |name: &str, href: &str| -> text::Item {
+        text::LinkBuilder::default()
+            .name(name.to_string())
+            .href(url::Url::parse(href).unwrap())
+            .make_autolink()
+            .build()
+            .unwrap()
+            .into()
+    };
+    let bare = |name: &str, href: &str| -> text::Item {
+        text::LinkBuilder::default()
+            .name(name.to_string())
+            .href(url::Url::parse(href).unwrap())
+            .make_bare()
+            .build()
+            .unwrap()
+            .into()
+    };
+    let def = |s: &str| text::ItemBuilder::Def(s.to_string()).build().unwrap();
+    let src = "See <https://example.com/a?b=1> or <user@example.com>.";
+    let p = parse(src);
+    assert_eq!(
+        &[
+            def("See "),
+            auto("https://example.com/a?b=1", "https://example.com/a?b=1"),
+            def(" or "),
+            auto("user@example.com", "mailto:user@example.com"),
+            def("."),
+        ],
+        p.items()
+    );
+    assert_eq!(src, p.to_string());
+    assert_eq!(
+        &[def("<not a link!> <a:b c>")],
+        parse("<not a link!> <a:b c>").items()
+    );
+    let src = "Visit www.example.com/help, or (https://example.com/a_(b)).";
+    let p = parse(src);
+    assert_eq!(
+        &[
+            def("Visit "),
+            bare("www.example.com/help", "http://www.example.com/help"),
+            def(", or ("),
+            bare("https://example.com/a_(b)", "https://example.com/a_(b)"),
+            def(")."),
+        ],
+        p.items()
+    );
+    assert_eq!(src, p.to_string());
+    assert_ne!(
+        p,
+        parse("Visit www.example.com/help, or (<https://example.com/a_(b)>).")
+    );
+    assert_eq!(
+        &[def("awww.example.com and www.a_b.c_d")],
+        parse("awww.example.com and www.a_b.c_d").items()
+    );
+    for src in [
+        "www.a.com*",
+        "www.a.com&amp;",
+        "www.a.com_ b",
+        "(www.a.com)\\\\",
+    ] {
+        let p = parse(src);
+        assert_eq!(p, parse(&p.to_string()), "{}", src);
+    }
+    assert_eq!(
+        &[bare("www.a.com", "http://www.a.com"), def("*")],
+        parse("www.a.com*").items()
+    );
+    let p = parse("http://x.y^a");
+    assert_eq!(&[def("http://x.y^a")], p.items());
+    assert_eq!(p, parse(&p.to_string()));
+    let p = parse("literal www\\.example.com");
+    assert_eq!(&[def("literal www.example.com")], p.items());
+    assert_eq!("literal www\\.example.com", p.to_string());
+    let doc = crate::Parser::parse(
+        &document::Parser::default().extensions(text::Extensions::commonmark()),
+        "https://example.com",
+    )
+    .unwrap();
+    match &doc.blocks()[0] {
+        document::Block::Paragraph(p) => assert_eq!(&[def("https://example.com")], p.items()),
+        _ => panic!("expected a paragraph"),
+    }
+    assert_eq!("https://example.com", doc.to_string());
+    let all = text::Extensions::commonmark()
+        .strikethrough(true)
+        .highlight(true)
+        .superscript(true)
+        .subscript(true)
+        .extended_autolinks(true);
+    assert_eq!(all, text::Extensions::default());
+    let p = text::ParagraphBuilder::default()
+        .content("www.example.com ~~a~~".to_string())
+        .build()
+        .unwrap();
+    assert_eq!("www\\.example.com \\~\\~a\\~\\~", p.to_string());
+}
+
+#[test]
+fn link_titles() {
+    let parse = |s: &str| text::Paragraph::from_str(s).unwrap();
+    let link = |name: &str, href: &str| {
+        text::LinkBuilder::default()
+            .name(name.to_string())
+            .href(url::Url::parse(href).unwrap())
+    };
+    let src = "[x](https://a.com/ \"Title\") and ![alt](https://a.com/i.png 'Say \"hi\"')";
+    let p = parse(src);
+    let image = link("alt", "https://a.com/i.png")
+        .title("Say \"hi\"")
+        .make_img()
+        .build()
+        .unwrap();
+    assert_eq!(
+        &[
+            link("x", "https://a.com/")
+                .title("Title")
+                .build()
+                .unwrap()
+                .into(),
+            text::ItemBuilder::Def(" and ".to_string()).build().unwrap(),
+            image.into(),
+        ],
+        p.items()
+    );
+    assert_eq!(
+        "[x](https://a.com/ \"Title\") and ![alt](https://a.com/i.png \"Say \\\"hi\\\"\")",
+        p.to_string()
+    );
+    assert_eq!(p, parse(&p.to_string()));
+    let p = parse("[a](<https://a.com/my file> (in\nparens)) [b](https://a.com/(c)d))");
+    let expected: text::Item = link("a", "https://a.com/my%20file")
+        .title("in\nparens")
+        .build()
+        .unwrap()
+        .into();
+    assert_eq!(expected, p.items()[0]);
+    if let text::Item::Link(l) = &p.items()[2] {
+        assert_eq!(23, l.span().range().len());
+        assert_eq!("[b](https://a.com/(c)d)", p.items()[2].to_string());
+    } else {
+        panic!("expected a link");
+    }
+    assert_eq!(")", p.items()[3].text());
+    let err = text::Item::from_str("[a](https://a.com \"open)").unwrap_err();
+    assert_eq!(&crate::ParseErrorKind::UnexpectedEnd, err.kind());
+    assert_eq!(
+        vec!["`\"`"],
+        err.expected().iter().map(|e| &**e).collect::<Vec<_>>()
+    );
+    let err = text::Item::from_str("[a](b c)").unwrap_err();
+    assert_eq!(&crate::ParseErrorKind::UnexpectedChar('c'), err.kind());
+    assert_eq!(Some((1, 7)), err.span().map(|s| (s.line(), s.column())));
+    let title = "a b ".repeat(200);
+    let p = parse(&format!("[a](https://a.com \"{}\") x", title));
+    match &p.items()[0] {
+        text::Item::Link(l) => assert_eq!(Some(title.as_str()), l.title()),
+        i => panic!("expected a link, got {:?}", i),
+    }
+    let many = "[a](https://a.com) ".repeat(20_000);
+    assert_eq!(
+        20_000,
+        document::Document::from_str(&many).unwrap().links().len()
+    );
+}
+
+#[test]
+fn reference_links() {
+    let src = "See [the docs][Docs], [docs][] and [Home].\n\n[not defined] and [x][missing]\n\n[docs]: <https://example.com/docs> \"Docs\"\n\n[home]: <https://example.com/>";
+    let doc = document::Document::from_str(src).unwrap();
+    let reference = |name: &str| {
+        text::ReferenceBuilder::default()
+            .name(name)
+            .href(url::Url::parse("https://example.com/").unwrap())
+            .build()
+            .unwrap()
+    };
+    let link = |name: &str, label: &str, style: text::RefStyle| -> text::Item {
+        text::LinkBuilder::default()
+            .name(name.to_string())
+            .reference(reference(label))
+            .style(style)
+            .build()
+            .unwrap()
+            .into()
+    };
+    let def = |s: &str| text::ItemBuilder::Def(s.to_string()).build().unwrap();
+    match &doc.blocks()[0] {
+        document::Block::Paragraph(p) => assert_eq!(
+            &[
+                def("See "),
+                link("the docs", "Docs", text::RefStyle::Full),
+                def(", "),
+                link("docs", "docs", text::RefStyle::Collapsed),
+                def(" and "),
+                link("Home", "Home", text::RefStyle::Shortcut),
+                def("."),
+            ],
+            p.items()
+        ),
+        _ => panic!("expected a paragraph"),
+    }
+    assert_eq!(src, doc.to_string());
+    let links = doc.links();
+    assert_eq!(4, links.len());
+    let docs = doc.resolve(&links[0]).unwrap();
+    assert_eq!("https://example.com/docs", docs.href().to_string());
+    assert_eq!("Docs", docs.title());
+    assert_eq!(Some(docs), doc.resolve(&links[1]));
+    assert_eq!(
+        Some(String::from("https://example.com/")),
+        doc.resolve(&links[2]).map(|r| r.href().to_string())
+    );
+    assert_eq!(
+        vec![link("x", "missing", text::RefStyle::Full)],
+        doc.dangling()
+            .into_iter()
+            .map(text::Item::Link)
+            .collect::<Vec<_>>()
+    );
+    assert!(doc.reference("  HOME ").is_some());
+    let doc = document::Document::from_str(
+        "- [Home]\n  - [x][missing]\n\n[home]: <https://example.com/>",
+    )
+    .unwrap();
+    let links = doc.links();
+    assert_eq!(2, links.len());
+    assert!(doc.resolve(&links[0]).is_some());
+    assert_eq!(vec![links[1].clone()], doc.dangling());
+    let p = text::Paragraph::from_str("[x] and [y][]").unwrap();
+    assert_eq!(def("[x] and "), p.items()[0]);
+    let p = text::ParagraphBuilder::default()
+        .push(link("x", "x", text::RefStyle::Shortcut))
+        .content("(y)".to_string())
+        .build()
+        .unwrap();
+    assert_eq!("[x][](y)", p.to_string());
+}
+
+#[test]
+fn relative_links() {
+    let src = "See [setup](./docs/setup.md?v=2#install), [usage](#usage) and ![logo](<img/the logo.png> \"Logo\")";
+    let p = text::Paragraph::from_str(src).unwrap();
+    let relative = |i: usize| match &p.items()[i] {
+        text::Item::Link(l) => match l.src() {
+            text::LinkSource::Relative(path) => path.clone(),
+            src => panic!("expected a relative source, got {:?}", src),
+        },
+        _ => panic!("expected a link"),
+    };
+    let setup = relative(1);
+    assert_eq!("./docs/setup.md", setup.path());
+    assert_eq!(Some("v=2"), setup.query());
+    assert_eq!(Some("install"), setup.fragment());
+    let usage = relative(3);
+    assert_eq!(
+        ("", None, Some("usage")),
+        (usage.path(), usage.query(), usage.fragment())
+    );
+    let logo = text::LinkBuilder::default()
+        .name("logo".to_string())
+        .relative(text::RelativePath::new("img/the logo.png"))
+        .title("Logo")
+        .make_img()
+        .build()
+        .unwrap();
+    assert_eq!(text::Item::Link(logo), p.items()[5]);
+    assert_eq!(src, p.to_string());
+    let err = text::Item::from_str("[x](http://[bad)").unwrap_err();
+    assert!(matches!(err.kind(), crate::ParseErrorKind::InvalidUrl(_)));
+    let src = "[Guide][guide]\n\n[guide]: <./docs/guide.md#start> \"Guide\"";
+    let doc = document::Document::from_str(src).unwrap();
+    let guide = doc.resolve(&doc.links()[0]).unwrap();
+    let expected = text::ReferenceBuilder::default()
+        .name("guide")
+        .relative(text::RelativePath::new("./docs/guide.md#start"))
+        .title("Guide")
+        .build()
+        .unwrap();
+    assert_eq!(&expected, guide);
+    assert_eq!(src, doc.to_string());
+    assert_eq!(
+        Ok(expected),
+        text::Reference::from_str("[guide]: ./docs/guide.md#start 'Guide'")
+    );
+}
+
+#[test]
+fn raw_html() {
+    let p = text::Paragraph::from_str("One<br>two <img width=\"80\" alt=logo>").unwrap();
+    assert_eq!(
+        text::ItemBuilder::Html("<br>".to_string()).build().unwrap(),
+        p.items()[1]
+    );
+    let img = p.items()[3].tag().unwrap();
+    assert_eq!("img", img.name());
+    assert_eq!(Some("80"), img.attribute("WIDTH"));
+    assert_eq!(Some("logo"), img.attribute("alt"));
+    assert_eq!("", p.items()[3].text());
+    let p = text::Paragraph::from_str("a <!-- x > y --> <b title='1 > 0'> c").unwrap();
+    assert_eq!("<!-- x > y -->", p.items()[1].to_string());
+    assert_eq!(
+        Some("1 > 0"),
+        p.items()[3].tag().unwrap().attribute("title")
+    );
+    for (src, html) in [
+        ("Text <!-- café --> more", "<!-- café -->"),
+        ("a <b title=\"日本\">", "<b title=\"日本\">"),
+        ("hi <!-- é) -->", "<!-- é) -->"),
+    ] {
+        let p = text::Paragraph::from_str(src).unwrap();
+        assert_eq!(html, p.items()[1].to_string());
+        assert_eq!(1, document::Document::from_str(src).unwrap().len());
+    }
+    let open = "<a b ".repeat(20_000) + "<!-- ".repeat(20_000).as_str();
+    let p = text::Paragraph::from_str(&open).unwrap();
+    assert!(p.items().iter().all(|i| i.tag().is_none()));
+    let src = "<details>\n<summary>More</summary>\n\n*Hidden*\n\n</details>\n\n<!-- one\n\ntwo -->\n\nText with <span>\n<span class=\"x\">\n\n  <div>\n  indented\n  </div>";
+    let doc = document::Document::from_str(src).unwrap();
+    let html = |i: usize| match &doc.blocks()[i] {
+        document::Block::Html(h) => h.clone(),
+        b => panic!("expected HTML, got {:?}", b),
+    };
+    let details = html(0);
+    assert_eq!(block::HtmlKind::Block, details.kind());
+    assert_eq!("<details>\n<summary>More</summary>", details.content());
+    assert_eq!(Some("details"), details.tag().as_ref().map(|t| t.name()));
+    assert!(matches!(doc.blocks()[1], document::Block::Paragraph(_)));
+    assert!(html(2).tag().unwrap().is_closing());
+    assert_eq!(block::HtmlKind::Comment, html(3).kind());
+    assert_eq!("<!-- one\n\ntwo -->", html(3).content());
+    assert!(matches!(doc.blocks()[4], document::Block::Paragraph(_)));
+    assert_eq!("  <div>\n  indented\n  </div>", html(5).content());
+    assert_eq!(src, doc.to_string());
+    for src in [
+        "<img src=\"a.png\">\n<img src=\"b.png\">",
+        "<span>\nx",
+        "<x-foo>\ny\n</x-foo>",
+    ] {
+        let doc = document::Document::from_str(src).unwrap();
+        assert!(
+            matches!(doc.blocks(), [document::Block::Html(h)] if h.kind() == block::HtmlKind::Tag)
+        );
+        assert_eq!(src, doc.to_string());
+        let built = block::HtmlBuilder::default().content(src).build().unwrap();
+        assert_eq!(block::HtmlKind::Tag, built.kind());
+    }
+    let built = block::HtmlBuilder::default()
+        .content("<pre>\n\n  x\n</pre>")
+        .build()
+        .unwrap();
+    assert_eq!(block::HtmlKind::Raw, built.kind());
+    assert!(block::HtmlBuilder::default().content("<b").build().is_err());
+}
+
+#[test]
+fn line_breaks() {
+    let src = "Jane Doe  \n12 Main St\\\nSpringfield\n*not  \nbroken*";
+    let p = text::Paragraph::from_str(src).unwrap();
+    let item = |b: text::ItemBuilder| b.build().unwrap();
+    let def = |s: &str| item(text::ItemBuilder::Def(s.to_string()));
+    let expected = text::ParagraphBuilder::default()
+        .content("Jane Doe".to_string())
+        .push(item(text::ItemBuilder::HardBreak(text::HardBreak::Spaces)))
+        .push(def("12 Main St"))
+        .push(item(text::ItemBuilder::HardBreak(
+            text::HardBreak::Backslash,
+        )))
+        .push(def("Springfield"))
+        .push(item(text::ItemBuilder::Break))
+        .push(item(text::ItemBuilder::Italic(vec![
+            def("not"),
+            item(text::ItemBuilder::HardBreak(text::HardBreak::Spaces)),
+            def("broken"),
+        ])))
+        .build()
+        .unwrap();
+    assert_eq!(expected, p);
+    assert_eq!(src, p.to_string());
+    assert_eq!(8..11, p.items()[1].span().range());
+    assert!(p.items()[5].is_break() && !p.items()[0].is_break());
+    assert_eq!(
+        "Jane Doe\\\n12 Main St\\\nSpringfield\n*not\\\nbroken*",
+        p.to_string_with(text::HardBreak::Backslash)
+    );
+    let p = text::Paragraph::from_str("a\\\\\nb\\").unwrap();
+    assert_eq!(
+        vec![def("a\\"), item(text::ItemBuilder::Break), def("b\\")],
+        p.items()
+    );
+    let built = text::ParagraphBuilder::default()
+        .content("soft  ".to_string())
+        .push(item(text::ItemBuilder::Break))
+        .content("break".to_string())
+        .build()
+        .unwrap();
+    assert_eq!("soft\nbreak", built.to_string());
+    let code = block::Code::from_str("`a\nb`").unwrap();
+    assert_eq!("a b", code.content.as_ref());
+}
+
+#[test]
+fn block_quotes() {
+    let src = "> Quoted text\nlazily continued.\n>\n> - one\n> - two\n>\n>> Nested [link]\n>\n> ```rust\n> fn main() {}\n> ```\n\n[link]: https://example.com/";
+    let doc = document::Document::from_str(src).unwrap();
+    let quote = match &doc.blocks()[0] {
+        document::Block::Quote(q) => q,
+        b => panic!("expected a quote, got {:?}", b),
+    };
+    let paragraph = text::ParagraphBuilder::default()
+        .content("Quoted text".to_string())
+        .push(text::ItemBuilder::Break.build().unwrap())
+        .content("lazily continued.".to_string())
+        .build()
+        .unwrap();
+    assert_eq!(4, quote.blocks().len());
+    assert_eq!(document::Block::Paragraph(paragraph), quote.blocks()[0]);
+    assert!(matches!(quote.blocks()[1], document::Block::List(_)));
+    assert!(matches!(quote.blocks()[3], document::Block::Code(_)));
+    match &quote.blocks()[2] {
+        document::Block::Quote(nested) => assert_eq!(1, nested.blocks().len()),
+        b => panic!("expected a nested quote, got {:?}", b),
+    }
+    let kinds = |src: &str| {
+        let doc = document::Document::from_str(src).unwrap();
+        let inner = match &doc.blocks()[0] {
+            document::Block::Quote(q) => q.blocks().len(),
+            b => panic!("expected a quote, got {:?}", b),
+        };
+        (inner, doc.len())
+    };
+    assert_eq!((1, 2), kinds("> ```\n> code\nafter"));
+    assert_eq!((1, 2), kinds(">     code\nlazy"));
+    assert_eq!((1, 1), kinds(">    text\nlazy"));
+    assert_eq!((2, 1), kinds("> ```\n> a\n> ```\n> b\nlazy"));
+    let fenced = document::Document::from_str("> ```\n> code\nafter").unwrap();
+    match &fenced.blocks()[0] {
+        document::Block::Quote(q) => match &q.blocks()[0] {
+            document::Block::Code(c) => assert_eq!("code", c.content()),
+            b => panic!("expected code, got {:?}", b),
+        },
+        b => panic!("expected a quote, got {:?}", b),
+    }
+    assert_eq!(0..101, quote.span().range());
+    let links = doc.links();
+    assert_eq!(1, links.len());
+    assert!(doc.resolve(&links[0]).is_some());
+    let again = document::Document::from_str(&doc.to_string()).unwrap();
+    assert_eq!(doc, again);
+    let built = block::QuoteBuilder::default()
+        .push(text::Paragraph::from_str("Outer").unwrap().into())
+        .push(
+            block::QuoteBuilder::default()
+                .push(text::Paragraph::from_str("Inner").unwrap().into())
+                .build()
+                .unwrap()
+                .into(),
+        )
+        .build()
+        .unwrap();
+    assert_eq!("> Outer\n>\n> > Inner", built.to_string());
+    let empty = block::QuoteBuilder::default().build().unwrap();
+    assert_eq!(">", empty.to_string());
+    assert_eq!(Ok(empty), block::Quote::from_str(">"));
+    let doc = document::Document::from_str("a\n\n>\n\nb").unwrap();
+    assert_eq!(3, doc.len());
+    assert_eq!("a\n\n>\n\nb", doc.to_string());
+    let deep = ">".repeat(3000) + " a";
+    let err = document::Document::from_str(&deep).unwrap_err();
+    assert_eq!(&crate::ParseErrorKind::TooDeep, err.kind());
+    let deep: String = (0..1000)
+        .map(|i| format!("{}- a\n", "  ".repeat(i)))
+        .collect();
+    let err = document::Document::from_str(&deep).unwrap_err();
+    assert_eq!(&crate::ParseErrorKind::TooDeep, err.kind());
+    let nested = "> ".repeat(crate::MAX_DEPTH) + "a";
+    assert!(document::Document::from_str(&nested).is_ok());
+}
+
+#[test]
+fn admonitions() {
+    let src = "> [!WARNING] Breaking change\n> Run `migrate` first.\n\n!!! tip \"Pro tip\"\n    Use the [docs].\n\n    - one\n    - two\n\n!!! danger\n\n[docs]: <https://example.com/docs>";
+    let doc = document::Document::from_str(src).unwrap();
+    let admonition = |i: usize| match &doc.blocks()[i] {
+        document::Block::Admonition(a) => a.clone(),
+        b => panic!("expected an admonition, got {:?}", b),
+    };
+    let warning = admonition(0);
+    assert_eq!(&block::AdmonitionKind::Warning, warning.kind());
+    assert_eq!(Some("Breaking change"), warning.title());
+    assert_eq!(block::AdmonitionStyle::Alert, warning.style());
+    assert_eq!(1, warning.blocks().len());
+    let tip = admonition(1);
+    assert_eq!(
+        (&block::AdmonitionKind::Tip, Some("Pro tip")),
+        (tip.kind(), tip.title())
+    );
+    assert_eq!(block::AdmonitionStyle::MkDocs, tip.style());
+    assert!(matches!(tip.blocks()[1], document::Block::List(_)));
+    let danger = admonition(2);
+    assert_eq!(
+        &block::AdmonitionKind::Custom("danger".into()),
+        danger.kind()
+    );
+    assert!(danger.blocks().is_empty());
+    assert_eq!(src, doc.to_string());
+    assert_eq!(1, doc.links().len());
+    assert!(doc.dangling().is_empty());
+    assert_eq!(
+        "!!! warning \"Breaking change\"\n    Run `migrate` first.",
+        warning.to_string_with(block::AdmonitionStyle::MkDocs)
+    );
+    assert_eq!(
+        "> [!TIP] Pro tip\n> Use the [docs].\n>\n> - one\n> - two",
+        tip.to_string_with(block::AdmonitionStyle::Alert)
+    );
+    let built = block::AdmonitionBuilder::default()
+        .kind(block::AdmonitionKind::Note)
+        .push(text::Paragraph::from_str("Plain note.").unwrap().into())
+        .build()
+        .unwrap();
+    assert_eq!("> [!NOTE]\n> Plain note.", built.to_string());
+    assert_eq!(
+        Ok(built),
+        block::Admonition::from_str("> [!note]\n> Plain note.")
+    );
+    assert!(matches!(
+        document::Document::from_str("> [!NOTE]: https://example.com")
+            .unwrap()
+            .blocks()[0],
+        document::Block::Quote(_)
+    ));
+    let p = text::ParagraphBuilder::default()
+        .content("!!! not an admonition".to_string())
+        .build()
+        .unwrap();
+    assert_eq!(p, text::Paragraph::from_str(&p.to_string()).unwrap());
+}
+
+#[test]
+fn thematic_breaks() {
+    let src = "Title\n---\n\nText\n* * *\n- one\n- two\n\n___\n\n- a\n- - -\n\n> quote\n***";
+    let doc = document::Document::from_str(src).unwrap();
+    let kinds: Vec<_> = doc
+        .iter()
+        .map(|b| match b {
+            document::Block::Heading(_) => "heading",
+            document::Block::Paragraph(_) => "paragraph",
+            document::Block::List(_) => "list",
+            document::Block::Quote(_) => "quote",
+            document::Block::ThematicBreak(_) => "break",
+            _ => "other",
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            "heading",
+            "paragraph",
+            "break",
+            "list",
+            "break",
+            "list",
+            "break",
+            "quote",
+            "break"
+        ],
+        kinds
+    );
+    let rule = |i: usize| match &doc.blocks()[i] {
+        document::Block::ThematicBreak(t) => (t.marker(), t.count()),
+        b => panic!("expected a thematic break, got {:?}", b),
+    };
+    assert_eq!(('*', 3), rule(2));
+    assert_eq!(('_', 3), rule(4));
+    assert_eq!(('-', 3), rule(6));
+    assert_eq!(16..21, doc.blocks()[2].span().range());
+    assert_eq!(doc, document::Document::from_str(&doc.to_string()).unwrap());
+    let built = block::ThematicBreakBuilder::default()
+        .marker('*')
+        .count(5)
+        .build()
+        .unwrap();
+    assert_eq!("*****", built.to_string());
+    assert_eq!(Ok(built.clone()), block::ThematicBreak::from_str("***** "));
+    let spaced = block::ThematicBreak::from_str("*  * ** *").unwrap();
+    assert!(spaced.spaced());
+    assert_ne!(built, spaced);
+    assert_eq!("* * * * *", spaced.to_string());
+    assert_eq!(
+        Ok(spaced),
+        block::ThematicBreakBuilder::default()
+            .marker('*')
+            .count(5)
+            .spaced(true)
+            .build()
+    );
+    assert!(block::ThematicBreakBuilder::default()
+        .marker('=')
+        .build()
+        .is_err());
+    assert!(block::ThematicBreak::from_str("--").is_err());
+    for src in ["Foo\n    ===", "Foo\n    ---  ", "Foo\n    -"] {
+        let doc = document::Document::from_str(src).unwrap();
+        assert!(matches!(doc.blocks(), [document::Block::Paragraph(_)]));
+        assert_eq!(doc, document::Document::from_str(&doc.to_string()).unwrap());
+    }
+}
+
+#[test]
+fn code_fences() {
+    let src = "Text\n    continued\n\n    fn main() {\n        run();\n\n    }\n\n~~~python\nprint(\"```\")\n~~~\n\n````\n```\n````";
+    let doc = document::Document::from_str(src).unwrap();
+    assert_eq!(4, doc.len());
+    assert!(matches!(doc.blocks()[0], document::Block::Paragraph(_)));
+    let code = |i: usize| match &doc.blocks()[i] {
+        document::Block::Code(c) => c.clone(),
+        b => panic!("expected code, got {:?}", b),
+    };
+    let indented = code(1);
+    assert_eq!(block::Fence::Indent, indented.fence());
+    assert_eq!(block::CodeKind::None, *indented.kind());
+    assert_eq!("fn main() {\n    run();\n\n}", indented.content());
+    let tilde = code(2);
+    assert_eq!(block::Fence::Tildes(3), tilde.fence());
+    assert_eq!(block::CodeKind::Python, *tilde.kind());
+    assert_eq!("print(\"```\")", tilde.content());
+    assert_eq!(block::Fence::Backticks(4), code(3).fence());
+    assert_eq!(doc, document::Document::from_str(&doc.to_string()).unwrap());
+    assert!(doc.to_string().ends_with(&src[20..]));
+    let built = block::CodeBuilder::default()
+        .content("let x = 1;")
+        .kind(block::CodeKind::Rust)
+        .fence(block::Fence::Tildes(4))
+        .build()
+        .unwrap();
+    assert_eq!("~~~~rust\nlet x = 1;\n~~~~", built.to_string());
+    assert_eq!(
+        Ok(built),
+        block::Code::from_str("~~~~rust\nlet x = 1;\n~~~~")
+    );
+    assert!(block::CodeBuilder::default()
+        .kind(block::CodeKind::None)
+        .fence(block::Fence::Tildes(2))
+        .build()
+        .is_err());
+    assert!(block::CodeBuilder::default()
+        .kind(block::CodeKind::Rust)
+        .fence(block::Fence::Indent)
+        .build()
+        .is_err());
+    let p = text::Paragraph::from_str("\\~~~ text").unwrap();
+    assert_eq!("\\~\\~\\~ text", p.to_string());
+    let src = "```\n```foo\n```";
+    let code = block::Code::from_str(src).unwrap();
+    assert_eq!("```foo", code.content());
+    let doc = document::Document::from_str(src).unwrap();
+    assert_eq!(1, doc.len());
+    assert_eq!(document::Block::Code(code), doc.blocks()[0]);
+    for (src, content, out) in [
+        ("~~~ ~\nx\n~~~", "x", "~~~ ~\nx\n~~~"),
+        (
+            "  ```\n  a\n   b\nc\n  ```",
+            "a\n b\nc",
+            "```\na\n b\nc\n```",
+        ),
+    ] {
+        let doc = document::Document::from_str(src).unwrap();
+        match doc.blocks() {
+            [document::Block::Code(c)] => assert_eq!(content, c.content()),
+            b => panic!("expected code, got {:?}", b),
+        }
+        assert_eq!(out, doc.to_string());
+        assert_eq!(doc, document::Document::from_str(out).unwrap());
+    }
+}
@@ -2,110 +2,1267 @@ use std::slice::Iter;
 
 use url::Url;
 
-use crate::{Element, ParseToken};
+use crate::{Element, ParseToken, Position, Span, Token};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Paragraph {}
+#[derive(Debug, Clone)]
+pub struct Paragraph {
+    pub(crate) items: Box<[Item]>,
+    pub(crate) extensions: Extensions,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Paragraph { items });
+
+impl Paragraph {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    /// Renders the paragraph with every hard line break written as `style`,
+    /// whichever way it was written before.
+    pub fn to_string_with(&self, style: HardBreak) -> String {
+        let mut items = self.items.clone();
+        restyle_breaks(&mut items, style);
+        render(&items, true, self.extensions)
+    }
+}
+
+/// Sets the style of the hard line breaks in `items`, nested ones included.
+fn restyle_breaks(items: &mut [Item], style: HardBreak) {
+    for item in items {
+        match item {
+            Item::HardBreak(b, _) => *b = style,
+            Item::Bold(c, _)
+            | Item::BoldItalic(c, _)
+            | Item::Italic(c, _)
+            | Item::Strikethrough(c, _)
+            | Item::Highlight(c, _)
+            | Item::Superscript(c, _)
+            | Item::Subscript(c, _) => restyle_breaks(c, style),
+            Item::Link(l) => restyle_breaks(&mut l.items, style),
+            _ => {}
+        }
+    }
+}
+
+impl Element for Paragraph {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
+    }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        while !iter.as_slice().is_empty() {
+            let (_, breaks) = crate::take_line(iter);
+            if breaks != 1 {
+                break;
+            }
+        }
+        let tokens = trim_end(&before[..before.len() - iter.as_slice().len()]);
+        let items = parse_inline(tokens, cx);
+        if items.is_empty() {
+            return Err(crate::ParseErrorKind::EmptyContent.into());
+        }
+        Ok(Paragraph {
+            items: items.into_boxed_slice(),
+            extensions: cx.extensions,
+            span: crate::consumed(before, iter),
+        })
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for Paragraph {
+    fn to_string(&self) -> String {
+        render(&self.items, true, self.extensions)
+    }
+}
+
+/// Renders `items` one after the other, escaping their text against
+/// `extensions`. `line_start` tells whether they start a line.
+fn render(items: &[Item], line_start: bool, extensions: Extensions) -> String {
+    let mut out = String::new();
+    let mut start = line_start;
+    for (i, item) in items.iter().enumerate() {
+        match item {
+            Item::Def(s, _) if matches!(items.get(i + 1), Some(Item::Break(_))) => {
+                out.push_str(escape(s, start, extensions).trim_end_matches(' '))
+            }
+            Item::Def(s, _) => {
+                let text = escape(s, start, extensions);
+                match i.checked_sub(1).and_then(|j| bare_text(&items[j])) {
+                    Some(url) if !bare_end(&url, &text) => out.push_str(&detach(&text)),
+                    _ => out.push_str(&text),
+                }
+            }
+            _ => out.push_str(&item.render(extensions)),
+        }
+        if let Item::Link(l) = item {
+            let text = render(&l.items, false, extensions);
+            let shortcut = ref_tail(l, &text).is_some_and(|t| t.is_empty());
+            let next = items
+                .get(i + 1)
+                .map(|n| render(std::slice::from_ref(n), false, extensions));
+            if shortcut && next.is_some_and(|n| n.starts_with(['(', '['])) {
+                out.push_str("[]");
+            }
+        }
+        start = item.is_break();
+    }
+    out
+}
+
+/// The text of `item` if it is a link rendered as a bare URL.
+fn bare_text(item: &Item) -> Option<String> {
+    match item {
+        Item::Link(l) => autolink_text(l).filter(|t| !t.starts_with('<')),
+        _ => None,
+    }
+}
+
+/// Whether the bare URL `url` still ends where it did with `text` rendered
+/// right after it.
+fn bare_end(url: &str, text: &str) -> bool {
+    bare_url(&format!("{}{}", url, text)).is_some_and(|(end, _)| end == url.len())
+}
+
+/// Rewrites the first word of escaped `text` so that a bare URL before it
+/// leaves it out: backslash escapes and `&` become entity references, which
+/// are trimmed from the end of the URL like the punctuation around them.
+fn detach(text: &str) -> String {
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == '<')
+        .unwrap_or(text.len());
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text[..end].chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => chars.next().unwrap_or(c),
+            '&' => '&',
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        match c {
+            '&' => out.push_str("&amp;"),
+            '*' => out.push_str("&ast;"),
+            '_' => out.push_str("&lowbar;"),
+            '\\' => out.push_str("&bsol;"),
+            '`' => out.push_str("&grave;"),
+            '[' => out.push_str("&lsqb;"),
+            ']' => out.push_str("&rsqb;"),
+            '=' => out.push_str("&equals;"),
+            '^' => out.push_str("&Hat;"),
+            _ => out.push(c),
+        }
+    }
+    out.push_str(&text[end..]);
+    out
+}
+
+/// The inline syntax extensions recognised on top of CommonMark: `~~` for
+/// strikethrough, `==` for highlighting, `^` for superscript, `~` for
+/// subscript and links from bare `www.` and `http(s)://` URLs. The default
+/// enables all of them, so that `FromStr` reads back everything the nodes can
+/// hold; [`Extensions::commonmark`] turns them off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Extensions {
+    strikethrough: bool,
+    highlight: bool,
+    superscript: bool,
+    subscript: bool,
+    extended_autolinks: bool,
+}
+
+impl Extensions {
+    /// Plain CommonMark, without any extensions.
+    pub fn commonmark() -> Self {
+        Self {
+            strikethrough: false,
+            highlight: false,
+            superscript: false,
+            subscript: false,
+            extended_autolinks: false,
+        }
+    }
+    /// GitHub Flavored Markdown, where both `~` and `~~` strike text through
+    /// and bare URLs are links.
+    pub fn gfm() -> Self {
+        Self::commonmark()
+            .strikethrough(true)
+            .extended_autolinks(true)
+    }
+    pub fn strikethrough(mut self, on: bool) -> Self {
+        self.strikethrough = on;
+        self
+    }
+    pub fn highlight(mut self, on: bool) -> Self {
+        self.highlight = on;
+        self
+    }
+    pub fn superscript(mut self, on: bool) -> Self {
+        self.superscript = on;
+        self
+    }
+    pub fn subscript(mut self, on: bool) -> Self {
+        self.subscript = on;
+        self
+    }
+    pub fn extended_autolinks(mut self, on: bool) -> Self {
+        self.extended_autolinks = on;
+        self
+    }
+    /// Whether a run of `n` `c` characters can open or close formatting.
+    fn delimits(&self, c: char, n: usize) -> bool {
+        match (c, n) {
+            ('*' | '_', _) => true,
+            ('~', 2) => self.strikethrough,
+            ('~', 1) => self.subscript || self.strikethrough,
+            ('=', 2) => self.highlight,
+            ('^', 1) => self.superscript,
+            _ => false,
+        }
+    }
+    /// Whether the formatting opened by a run of `n` `c` characters may not
+    /// hold any whitespace, as with superscript and subscript.
+    fn spaceless(&self, c: char, n: usize) -> bool {
+        c == '^' || (c == '~' && n == 1 && self.subscript)
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self {
+            strikethrough: true,
+            highlight: true,
+            superscript: true,
+            subscript: true,
+            extended_autolinks: true,
+        }
+    }
+}
+
+/// Inline content gathered before emphasis is resolved: finished items, and
+/// delimiter runs that may still open or close emphasis.
+enum Node {
+    Item(Item),
+    Delim(Delim),
+}
+
+/// A run of `*`, `_` or the delimiters of an extension, of which `n`
+/// delimiters starting at `start` are not used up yet.
+struct Delim {
+    c: char,
+    n: usize,
+    len: usize,
+    open: bool,
+    close: bool,
+    start: Position,
+}
+
+impl Delim {
+    /// Reads the delimiter run at `i` in `tokens`, working out whether it can
+    /// open or close emphasis from the characters on either side of it.
+    fn new(tokens: &[Token], i: usize, c: char, n: usize) -> Self {
+        let before = i
+            .checked_sub(1)
+            .and_then(|j| tokens[j].to_string().chars().last());
+        let after = tokens.get(i + 1).and_then(|t| t.to_string().chars().next());
+        let (open, close) = flanking(c, before, after);
+        Self {
+            c,
+            n,
+            len: n,
+            open,
+            close,
+            start: tokens[i].span.start(),
+        }
+    }
+    fn at(&self, k: usize) -> Position {
+        let mut p = self.start;
+        p.advance(&self.c.to_string().repeat(k));
+        p
+    }
+    /// Whether the delimiters can close emphasis opened by `opener`. Runs that
+    /// can both open and close only pair up when their lengths do not add up
+    /// to a multiple of three, unless both are multiples of three. Extensions
+    /// are only closed by a run as long as the one that opened them.
+    fn closes(&self, opener: &Delim) -> bool {
+        if !matches!(self.c, '*' | '_') {
+            return opener.open && opener.c == self.c && opener.len == self.len;
+        }
+        opener.open
+            && opener.c == self.c
+            && !((opener.close || self.open)
+                && (opener.len + self.len).is_multiple_of(3)
+                && !(opener.len.is_multiple_of(3) && self.len.is_multiple_of(3)))
+    }
+}
+
+/// Whether a run of `c` between the characters `before` and `after` can open
+/// and close emphasis, where a missing character counts as whitespace.
+fn flanking(c: char, before: Option<char>, after: Option<char>) -> (bool, bool) {
+    let space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    let punct = |c: Option<char>| {
+        c.is_some_and(|c| c.is_ascii_punctuation() || !(c.is_alphanumeric() || c.is_whitespace()))
+    };
+    let left = !space(after) && (!punct(after) || space(before) || punct(before));
+    let right = !space(before) && (!punct(before) || space(after) || punct(after));
+    if c == '_' {
+        (
+            left && (!right || punct(before)),
+            right && (!left || punct(after)),
+        )
+    } else {
+        (left, right)
+    }
+}
+
+/// Text being gathered from tokens, with the span of everything but trailing
+/// spaces.
+#[derive(Default)]
+struct Text {
+    content: String,
+    span: Option<Span>,
+}
+
+impl Text {
+    fn push(&mut self, t: &Token) {
+        self.content.push_str(&t.to_string());
+        match self.span {
+            Some(_) if matches!(t.kind, ParseToken::RepeatSpecial(' ', _)) => {}
+            _ => self.span = Some(self.span.map_or(t.span, |s| s.to(t.span))),
+        }
+    }
+    /// Moves the text gathered so far into `nodes` with its escapes and
+    /// entity references decoded, dropping trailing spaces at the end of a
+    /// line.
+    fn flush(&mut self, nodes: &mut Vec<Node>, line_end: bool) {
+        let content = std::mem::take(&mut self.content);
+        let content = if line_end {
+            content.trim_end()
+        } else {
+            &content
+        };
+        if let Some(span) = self.span.take().filter(|_| !content.is_empty()) {
+            nodes.push(Node::Item(Item::Def(decode(content).into(), span)));
+        }
+    }
+}
+
+/// Parses the inline content of a paragraph. Constructs that fail to parse
+/// are kept as literal text, and any errors raised along the way are added to
+/// the warnings in `cx`.
+pub(crate) fn parse_inline(tokens: &[Token], cx: &mut crate::Context) -> Vec<Item> {
+    inline(tokens, true, cx)
+}
+
+/// Parses the text of a link, which unlike a paragraph keeps the spaces at
+/// its start and end.
+fn parse_link_text(tokens: &[Token], cx: &mut crate::Context) -> Vec<Item> {
+    inline(tokens, false, cx)
+}
+
+/// Parses inline content, leaving out the spaces around it if `trim` is set.
+fn inline(tokens: &[Token], trim: bool, cx: &mut crate::Context) -> Vec<Item> {
+    let mut tokens = split_parens(tokens);
+    let mut nodes = Vec::new();
+    let mut text = Text::default();
+    let mut line_start = trim;
+    let mut missing_html = Vec::new();
+    let mut closing = true;
+    let mut i = 0;
+    while i < tokens.len() {
+        if escapes(&tokens, i) {
+            if let Some(rest) = split_at(&mut tokens[i + 1], 1) {
+                tokens.insert(i + 2, rest);
+            }
+            text.push(&tokens[i]);
+            text.push(&tokens[i + 1]);
+            line_start = false;
+            i += 2;
+            continue;
+        }
+        if let (
+            Some(&ParseToken::RepeatSpecial('\\', n)),
+            Some(ParseToken::RepeatSpecial('\n', _)),
+        ) = (crate::kind(&tokens, i), crate::kind(&tokens, i + 1))
+        {
+            if n % 2 == 1 {
+                if let Some(rest) = split_at(&mut tokens[i], n - 1) {
+                    text.push(&tokens[i]);
+                    tokens.insert(i + 1, rest);
+                    i += 1;
+                }
+                text.flush(&mut nodes, true);
+                let span = tokens[i].span.to(tokens[i + 1].span);
+                nodes.push(Node::Item(Item::HardBreak(HardBreak::Backslash, span)));
+                line_start = true;
+                i += 2;
+                continue;
+            }
+        }
+        let auto = match tokens[i].kind {
+            ParseToken::RepeatSpecial('<', 1) => {
+                autolink(&mut tokens, i).or_else(|| inline_html(&mut tokens, i, &mut missing_html))
+            }
+            ParseToken::String(_) if cx.extensions.extended_autolinks => bare_link(&mut tokens, i),
+            _ => None,
+        };
+        let tokens = tokens.as_slice();
+        let t = &tokens[i];
+        let rest = &tokens[i..];
+        // Once no `]` is left, none of the brackets after it can open a link.
+        if closing && matches!(t.kind, ParseToken::RepeatSpecial('[' | '!', 1)) {
+            closing = rest
+                .iter()
+                .any(|t| matches!(t.kind, ParseToken::RepeatSpecial(']', _)));
+        }
+        let found = match &t.kind {
+            _ if auto.is_some() => auto,
+            ParseToken::RepeatSpecial(' ', _) if line_start => {
+                i += 1;
+                continue;
+            }
+            ParseToken::RepeatSpecial('\n', _) => {
+                let item = match trailing_spaces(&tokens[..i]) {
+                    Some(start) => {
+                        Item::HardBreak(HardBreak::Spaces, Span::new(start, t.span.end()))
+                    }
+                    None => Item::Break(t.span),
+                };
+                Some((item, 1))
+            }
+            ParseToken::RepeatSpecial(c, n) if cx.extensions.delimits(*c, *n) => {
+                text.flush(&mut nodes, false);
+                nodes.push(Node::Delim(Delim::new(tokens, i, *c, *n)));
+                line_start = false;
+                i += 1;
+                continue;
+            }
+            ParseToken::RepeatSpecial('`', n) => code_span(rest, *n),
+            ParseToken::RepeatSpecial('[', 1) if closing => link(rest, false, cx),
+            ParseToken::RepeatSpecial('!', 1) if closing => match crate::kind(rest, 1) {
+                Some(ParseToken::RepeatSpecial('[', 1)) => link(rest, true, cx),
+                _ => None,
+            },
+            _ => None,
+        };
+        line_start = matches!(t.kind, ParseToken::RepeatSpecial('\n', _));
+        match found {
+            Some((item, len)) => {
+                text.flush(&mut nodes, item.is_break());
+                nodes.push(Node::Item(item));
+                i += len;
+            }
+            None => {
+                text.push(t);
+                i += 1;
+            }
+        }
+    }
+    text.flush(&mut nodes, trim);
+    emphasis(nodes, cx.extensions)
+}
+
+/// Where the two or more spaces ending `tokens` start, if they do end with
+/// that many. Text tokens keep the spaces that follow their words.
+fn trailing_spaces(tokens: &[Token]) -> Option<Position> {
+    let last = tokens.last()?;
+    let text = last.to_string();
+    let words = text.trim_end_matches(' ');
+    if text.len() - words.len() < 2 {
+        return None;
+    }
+    let mut start = last.span.start();
+    start.advance(words);
+    Some(start)
+}
+
+/// Copies `tokens`, splitting runs of `)` and `]` into single characters so
+/// that link destinations and labels can end anywhere in them.
+fn split_parens(tokens: &[Token]) -> Vec<Token> {
+    let mut out = Vec::with_capacity(tokens.len());
+    for t in tokens {
+        let mut t = t.clone();
+        if matches!(t.kind, ParseToken::RepeatSpecial(')' | ']', _)) {
+            while let Some(rest) = split_at(&mut t, 1) {
+                out.push(t);
+                t = rest;
+            }
+        }
+        out.push(t);
+    }
+    out
+}
+
+/// Whether the token at `i` is a backslash escaping the punctuation character
+/// that follows it. Of a run of backslashes, all but the last escape each
+/// other.
+fn escapes(tokens: &[Token], i: usize) -> bool {
+    match (crate::kind(tokens, i), crate::kind(tokens, i + 1)) {
+        (Some(ParseToken::RepeatSpecial('\\', n)), Some(ParseToken::RepeatSpecial(c, _))) => {
+            n % 2 == 1 && c.is_ascii_punctuation()
+        }
+        _ => false,
+    }
+}
+
+/// Shortens `t` to its first `k` characters, returning a token for the rest
+/// of it if there is any. Runs of repeated characters stay runs, anything
+/// else is split into plain text.
+pub(crate) fn split_at(t: &mut Token, k: usize) -> Option<Token> {
+    let text = t.to_string();
+    let at = text.char_indices().nth(k).filter(|_| k > 0)?.0;
+    let (head, tail) = match t.kind {
+        ParseToken::RepeatSpecial(c, n) => (
+            ParseToken::RepeatSpecial(c, k),
+            ParseToken::RepeatSpecial(c, n - k),
+        ),
+        _ => (
+            ParseToken::String(text[..at].to_string()),
+            ParseToken::String(text[at..].to_string()),
+        ),
+    };
+    let mut mid = t.span.start();
+    mid.advance(&text[..at]);
+    let rest = Token {
+        kind: tail,
+        span: Span::new(mid, t.span.end()),
+    };
+    t.kind = head;
+    t.span = Span::new(t.span.start(), mid);
+    Some(rest)
+}
+
+/// Resolves the backslash escapes and entity references in `s`.
+pub(crate) fn decode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(['\\', '&']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let escaped = rest[1..].chars().next().filter(char::is_ascii_punctuation);
+        if let (Some(c), true) = (escaped, rest.starts_with('\\')) {
+            out.push(c);
+            rest = &rest[1 + c.len_utf8()..];
+        } else if let Some((text, len)) = entity(rest) {
+            out.push_str(&text);
+            rest = &rest[len..];
+        } else {
+            out.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decodes the entity reference, such as `&amp;`, `&#39;` or `&#x27;`, at the
+/// start of `s`, returning the text it stands for and its length.
+fn entity(s: &str) -> Option<(String, usize)> {
+    let body = s.strip_prefix('&')?;
+    let end = body.find(';').filter(|end| *end <= 32)?;
+    let name = &body[..end];
+    let text = if let Some(num) = name.strip_prefix('#') {
+        let code = match num.strip_prefix(['x', 'X']) {
+            Some(hex) if (1..=6).contains(&hex.len()) => u32::from_str_radix(hex, 16).ok()?,
+            None if (1..=7).contains(&num.len()) && num.bytes().all(|b| b.is_ascii_digit()) => {
+                num.parse().ok()?
+            }
+            _ => return None,
+        };
+        let c = char::from_u32(code).filter(|c| *c != '\0');
+        c.unwrap_or(char::REPLACEMENT_CHARACTER).to_string()
+    } else if name.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        crate::entities::lookup(name)?.to_string()
+    } else {
+        return None;
+    };
+    Some((text, end + 2))
+}
+
+/// Escapes the characters of plain `text` that would otherwise be read back as
+/// markup under `extensions`, assuming the worst of whatever is rendered around
+/// it. Brackets are escaped unless they pair up, and block markers are escaped
+/// too where the text starts a line, as it does at `line_start`.
+fn escape(text: &str, line_start: bool, extensions: Extensions) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut paired = vec![false; chars.len()];
+    let mut open = Vec::new();
+    for (i, c) in chars.iter().enumerate() {
+        match c {
+            '[' => open.push(i),
+            ']' => {
+                if let Some(j) = open.pop() {
+                    paired[i] = true;
+                    paired[j] = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut start = line_start;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let run = chars[i..].iter().take_while(|d| **d == c).count();
+        let before = i.checked_sub(1).map_or(Some('.'), |j| Some(chars[j]));
+        let after = chars.get(i + run).copied();
+        let digits = chars[i..].iter().take_while(|d| d.is_ascii_digit()).count();
+        let word = i
+            .checked_sub(1)
+            .is_none_or(|j| chars[j].is_whitespace() || matches!(chars[j], '*' | '_' | '~' | '('));
+        if word && extensions.extended_autolinks && matches!(c, 'w' | 'h') {
+            let rest: String = chars[i..].iter().collect();
+            if ["www.", "http://", "https://"]
+                .iter()
+                .any(|p| rest.starts_with(p))
+            {
+                let mark = rest.find(['.', ':']).unwrap();
+                out.push_str(&rest[..mark]);
+                out.push('\\');
+                i += rest[..mark].chars().count();
+                out.push(chars[i]);
+                start = false;
+                i += 1;
+                continue;
+            }
+        }
+        if start && (1..=9).contains(&digits) {
+            let marker = chars.get(i + digits).filter(|d| matches!(d, '.' | ')'));
+            if let Some(m) = marker.filter(|_| chars.get(i + digits + 1).is_none_or(|d| *d == ' '))
+            {
+                out.extend(&chars[i..i + digits]);
+                out.push('\\');
+                out.push(*m);
+                start = false;
+                i += digits + 1;
+                continue;
+            }
+        }
+        let escaped = match c {
+            '\\' => run > 1 || after.is_none_or(|a| a.is_ascii_punctuation()),
+            '`' => true,
+            '=' | '-' if start && underline(&chars[i..]) => true,
+            '*' | '_' | '~' | '=' | '^' if extensions.delimits(c, run) => {
+                let (open, close) = flanking(c, before, after.or(Some('.')));
+                open || close || (start && c != '_')
+            }
+            '[' | ']' => {
+                !paired[i]
+                    || after.is_none_or(|a| matches!(a, '(' | '[' | ':'))
+                    || (start && c == '[' && after == Some('!'))
+            }
+            '!' => start && run == 3 && after == Some(' '),
+            '~' => start && run >= 3,
+            '&' => entity(&chars[i..chars.len().min(i + 34)].iter().collect::<String>()).is_some(),
+            '#' => start && run <= 6 && after.is_none_or(|a| a == ' '),
+            '<' if run == 1 => {
+                let inner: String = chars[i + 1..].iter().take_while(|d| **d != '>').collect();
+                let rest: String = chars[i..].iter().collect();
+                let line = rest.lines().next().unwrap_or_default();
+                (chars.len() > i + 1 + inner.chars().count() && autolink_target(&inner).is_some())
+                    || crate::html::inline_len(&rest).is_some()
+                    || (start && crate::html::block_start(line).is_some())
+            }
+            '>' => start,
+            '-' | '+' => start && after.is_none_or(|a| a == ' '),
+            _ => false,
+        };
+        for _ in 0..run {
+            if escaped {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        start = c == '\n' || (start && c == ' ');
+        i += run;
+    }
+    out
+}
+
+/// Whether the line `chars` start would be read as a setext underline, a
+/// single run of its first character followed by nothing but spaces.
+fn underline(chars: &[char]) -> bool {
+    let line = chars.split(|c| *c == '\n').next().unwrap_or_default();
+    let run = line.iter().take_while(|c| **c == chars[0]).count();
+    line[run..].iter().all(|c| matches!(c, ' ' | '\t'))
+}
+
+/// Pairs up delimiter runs into emphasis and the formatting of `extensions`
+/// following the CommonMark rules, closing each run with the nearest opener
+/// before it. Delimiters left over are kept as text.
+fn emphasis(mut nodes: Vec<Node>, extensions: Extensions) -> Vec<Item> {
+    let mut c = 0;
+    while c < nodes.len() {
+        let opener = match &nodes[c] {
+            Node::Delim(closer) if closer.close => {
+                let spaceless = extensions.spaceless(closer.c, closer.len);
+                (0..c).rev().find(|o| match &nodes[*o] {
+                    Node::Delim(d) => closer.closes(d) && !(spaceless && spaced(&nodes[o + 1..c])),
+                    _ => false,
+                })
+            }
+            _ => None,
+        };
+        let (o, delim) = match (opener, &nodes[c]) {
+            (Some(o), Node::Delim(d)) => (o, d.c),
+            _ => {
+                c += 1;
+                continue;
+            }
+        };
+        let children = literal(nodes.drain(o + 1..c).collect());
+        let (k, start, end) = match nodes.get_mut(o..o + 2) {
+            Some([Node::Delim(opener), Node::Delim(closer)]) => {
+                let k = if opener.n >= 2 && closer.n >= 2 { 2 } else { 1 };
+                opener.n -= k;
+                let start = opener.at(opener.n);
+                closer.start = closer.at(k);
+                closer.n -= k;
+                (k, start, closer.start)
+            }
+            _ => unreachable!("emphasis is closed between two delimiter runs"),
+        };
+        let span = Span::new(start, end);
+        let bold = k == 2;
+        let item = match delim {
+            '~' if bold || !extensions.subscript => {
+                Item::Strikethrough(children.into_boxed_slice(), span)
+            }
+            '~' => Item::Subscript(children.into_boxed_slice(), span),
+            '=' => Item::Highlight(children.into_boxed_slice(), span),
+            '^' => Item::Superscript(children.into_boxed_slice(), span),
+            _ => match <[Item; 1]>::try_from(children) {
+                Ok([Item::Bold(c, _)]) if !bold => Item::BoldItalic(c, span),
+                Ok(child) if bold => Item::Bold(Box::new(child), span),
+                Ok(child) => Item::Italic(Box::new(child), span),
+                Err(c) if bold => Item::Bold(c.into_boxed_slice(), span),
+                Err(c) => Item::Italic(c.into_boxed_slice(), span),
+            },
+        };
+        nodes.insert(o + 1, Node::Item(item));
+        c = o + 2;
+        if matches!(&nodes[c], Node::Delim(d) if d.n == 0) {
+            nodes.remove(c);
+        }
+        if matches!(&nodes[o], Node::Delim(d) if d.n == 0) {
+            nodes.remove(o);
+            c -= 1;
+        }
+    }
+    literal(nodes)
+}
+
+/// Whether any of `nodes` holds whitespace.
+fn spaced(nodes: &[Node]) -> bool {
+    nodes.iter().any(|n| match n {
+        Node::Item(i) if i.is_break() => true,
+        Node::Item(i) => i.text().contains(char::is_whitespace),
+        Node::Delim(_) => false,
+    })
+}
+
+/// Turns the delimiters left in `nodes` into text, merging it with the text
+/// around it.
+fn literal(nodes: Vec<Node>) -> Vec<Item> {
+    let mut items: Vec<Item> = Vec::new();
+    for node in nodes {
+        let item = match node {
+            Node::Item(item) => item,
+            Node::Delim(d) => Item::Def(
+                d.c.to_string().repeat(d.n).into_boxed_str(),
+                Span::new(d.start, d.at(d.n)),
+            ),
+        };
+        match (items.last_mut(), item) {
+            (Some(Item::Def(text, span)), Item::Def(s, end)) => {
+                *text = format!("{}{}", text, s).into_boxed_str();
+                *span = span.to(end);
+            }
+            (_, item) => items.push(item),
+        }
+    }
+    items
+}
+
+/// Parses the code span opened by the `n` backticks at the start of `tokens`.
+/// It is closed by the next run of exactly `n` backticks. Line breaks in it
+/// become spaces, and a single space is stripped from both ends when the
+/// content has one on each side and is not all spaces.
+fn code_span(tokens: &[Token], n: usize) -> Option<(Item, usize)> {
+    let end = (1..tokens.len()).find(|j| tokens[*j].kind == ParseToken::RepeatSpecial('`', n))?;
+    let mut content = String::new();
+    let mut line_start = false;
+    for t in &tokens[1..end] {
+        match t.kind {
+            ParseToken::RepeatSpecial('\n', _) => content.push(' '),
+            ParseToken::RepeatSpecial(' ', _) if line_start => {}
+            _ => content.push_str(&t.to_string()),
+        }
+        line_start = matches!(t.kind, ParseToken::RepeatSpecial('\n', _));
+    }
+    let content = match content.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+        Some(inner) if !content.trim_start_matches(' ').is_empty() => inner,
+        _ => &content,
+    };
+    let span = tokens[0].span.to(tokens[end].span);
+    Some((Item::Code(content.into(), span), end + 1))
+}
+
+/// Parses the link or image at the start of `tokens`. Brackets are plain text
+/// often enough that failing to parse them is only reported to the warnings in
+/// `cx` when the text is followed by the `(` of an inline link.
+fn link(tokens: &[Token], img: bool, cx: &mut crate::Context) -> Option<(Item, usize)> {
+    let rest = &tokens[1..];
+    let mut iter = rest.iter();
+    match process_link_item(&mut iter, img, cx) {
+        Ok(mut item) => {
+            let len = tokens.len() - iter.as_slice().len();
+            item.set_span(crate::span_of(&tokens[..len]));
+            Some((item, len))
+        }
+        Err(e) => {
+            let text = &rest[usize::from(img)..];
+            if link_text_end(text).is_some_and(|end| {
+                matches!(
+                    crate::kind(text, end + 1),
+                    Some(ParseToken::RepeatSpecial('(', _))
+                )
+            }) {
+                cx.warnings.push(e.locate(rest, &iter));
+            }
+            None
+        }
+    }
+}
+
+/// Parses the autolink starting with the `<` at `i` in `tokens`, splitting off
+/// whatever follows its closing `>`.
+fn autolink(tokens: &mut Vec<Token>, i: usize) -> Option<(Item, usize)> {
+    let close = i
+        + 1
+        + tokens[i + 1..]
+            .iter()
+            .position(|t| matches!(t.kind, ParseToken::RepeatSpecial('>' | '<' | ' ' | '\n', _)))?;
+    if !matches!(tokens[close].kind, ParseToken::RepeatSpecial('>', _)) {
+        return None;
+    }
+    let inner = &tokens[i + 1..close];
+    let text: String = inner.iter().map(Token::to_string).collect();
+    let href = autolink_target(&text)?;
+    let text_span = crate::span_of(inner);
+    if let Some(rest) = split_at(&mut tokens[close], 1) {
+        tokens.insert(close + 1, rest);
+    }
+    let link = Link {
+        items: Box::new([Item::Def(text.into_boxed_str(), text_span)]),
+        src: LinkSource::Url(href),
+        title: None,
+        img: false,
+        auto: true,
+        bare: false,
+        style: RefStyle::Full,
+        span: crate::span_of(&tokens[i..=close]),
+    };
+    Some((link.into(), close - i + 1))
+}
+
+/// The destination of an autolink around `s`, which has to be an absolute
+/// URI or an email address.
+fn autolink_target(s: &str) -> Option<Url> {
+    if let Some((scheme, rest)) = s.split_once(':') {
+        let uri = (2..=32).contains(&scheme.len())
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+            && !rest.contains(|c: char| c.is_ascii_control() || matches!(c, ' ' | '<' | '>'));
+        if uri {
+            return Url::parse(s).ok();
+        }
+    }
+    let (local, domain) = s.split_once('@')?;
+    let label = |l: &str| {
+        (1..=63).contains(&l.len())
+            && !l.starts_with('-')
+            && !l.ends_with('-')
+            && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    let email = !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+        && domain.split('.').all(label);
+    email.then(|| Url::parse(&format!("mailto:{}", s)).ok())?
+}
+
+/// Parses the bare URL starting a word in the text token at `i` in `tokens`,
+/// splitting off whatever follows it. When the URL starts further into the
+/// token, the token is split there instead, to be picked up again from the
+/// next one.
+fn bare_link(tokens: &mut Vec<Token>, i: usize) -> Option<(Item, usize)> {
+    let word = tokens[i].to_string();
+    let before = i
+        .checked_sub(1)
+        .and_then(|j| tokens[j].to_string().chars().last());
+    let mut after = None;
+    let (k, text, href) = word.char_indices().find_map(|(k, c)| {
+        let boundary = match k {
+            0 => before.is_none_or(|b| b.is_whitespace() || matches!(b, '*' | '_' | '~' | '(')),
+            _ => word[..k].ends_with(' '),
+        };
+        if !boundary || !matches!(c, 'w' | 'h') {
+            return None;
+        }
+        let after = after.get_or_insert_with(|| {
+            let mut s = String::new();
+            for t in &tokens[i + 1..] {
+                s.push_str(&t.to_string());
+                if s.contains(|c: char| c.is_whitespace() || c == '<') {
+                    break;
+                }
+            }
+            s
+        });
+        let mut text = format!("{}{}", &word[k..], after);
+        let (end, href) = bare_url(&text)?;
+        text.truncate(end);
+        Some((k, text, href))
+    })?;
+    if k > 0 {
+        let rest = split_at(&mut tokens[i], word[..k].chars().count())?;
+        tokens.insert(i + 1, rest);
+        return None;
+    }
+    let j = take_chars(tokens, i, text.chars().count());
+    let span = crate::span_of(&tokens[i..=j]);
+    let link = Link {
+        items: Box::new([Item::Def(text.into_boxed_str(), span)]),
+        src: LinkSource::Url(href),
+        title: None,
+        img: false,
+        auto: true,
+        bare: true,
+        style: RefStyle::Full,
+        span,
+    };
+    Some((link.into(), j - i + 1))
+}
+
+/// Parses the raw HTML starting with the `<` at `i` in `tokens`, splitting off
+/// whatever follows it. The ends of HTML that were looked for all the way to
+/// the end of `tokens` without being found are kept in `missing`, so that each
+/// is only ever looked for once.
+fn inline_html(
+    tokens: &mut Vec<Token>,
+    i: usize,
+    missing: &mut Vec<&'static str>,
+) -> Option<(Item, usize)> {
+    let next = tokens.get(i + 1)?.to_string();
+    if !next.starts_with(|c: char| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?')) {
+        return None;
+    }
+    let mut rest = String::from("<");
+    let mut quote = None;
+    let mut seen_end = false;
+    for t in &tokens[i + 1..] {
+        let from = rest.char_indices().rev().nth(1).map_or(0, |(j, _)| j);
+        let text = t.to_string();
+        rest.push_str(&text);
+        let end = html_end(&rest);
+        if missing.contains(&end) {
+            return None;
+        }
+        if end == ">" && !next.starts_with('!') {
+            // A tag can only go on past a `<` or its first `>` inside a quoted
+            // attribute value.
+            for c in text.chars() {
+                match (quote, c) {
+                    (None, '"' | '\'') => quote = Some(c),
+                    (Some(q), _) if q == c => quote = None,
+                    (None, '<') => return None,
+                    _ => {}
+                }
+            }
+        }
+        if !rest[from..].contains(end) {
+            continue;
+        }
+        seen_end = true;
+        if let Some(len) = crate::html::inline_len(&rest) {
+            let html = &rest[..len];
+            let j = take_chars(tokens, i, html.chars().count());
+            let span = crate::span_of(&tokens[i..=j]);
+            return Some((Item::Html(html.into(), span), j - i + 1));
+        }
+        if end != ">" || next.starts_with('!') || quote.is_none() {
+            return None;
+        }
+    }
+    if !seen_end {
+        missing.push(html_end(&rest));
+    }
+    None
+}
+
+/// What ends the raw HTML that `s` starts.
+#[inline]
+fn html_end(s: &str) -> &'static str {
+    if s.starts_with("<!--") {
+        "-->"
+    } else if s.starts_with("<?") {
+        "?>"
+    } else if s.starts_with("<![") {
+        "]]>"
+    } else {
+        ">"
+    }
+}
+
+/// Splits the tokens from `i` on so that the `n`th character after the start
+/// of the token at `i` ends a token, returning the index of that token.
+fn take_chars(tokens: &mut Vec<Token>, i: usize, n: usize) -> usize {
+    let mut left = n;
+    let mut j = i;
+    loop {
+        let len = tokens[j].to_string().chars().count();
+        if len >= left {
+            if let Some(rest) = split_at(&mut tokens[j], left) {
+                tokens.insert(j + 1, rest);
+            }
+            return j;
+        }
+        left -= len;
+        j += 1;
+    }
+}
+
+/// The length of the URL starting with `www.`, `http://` or `https://` at the
+/// start of `s` that is linked without any markup in GitHub Flavored Markdown,
+/// along with its destination. Trailing punctuation, unbalanced parentheses
+/// and entity references are left out of the URL.
+fn bare_url(s: &str) -> Option<(usize, Url)> {
+    let prefix = ["www.", "http://", "https://"]
+        .into_iter()
+        .find(|p| s.starts_with(p))?;
+    let mut end = s
+        .find(|c: char| c.is_whitespace() || c == '<')
+        .unwrap_or(s.len());
+    loop {
+        let url = &s[..end];
+        match url.chars().last() {
+            Some('?' | '!' | '.' | ',' | ':' | '*' | '_' | '~' | '\'' | '"') => end -= 1,
+            Some(')') if url.matches(')').count() > url.matches('(').count() => end -= 1,
+            Some(';') => match url[..end - 1].rfind('&') {
+                Some(amp)
+                    if amp + 2 < end
+                        && url[amp + 1..end - 1]
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric()) =>
+                {
+                    end = amp
+                }
+                _ => break,
+            },
+            _ => break,
+        }
+    }
+    let url = &s[..end];
+    let host = if prefix == "www." {
+        url
+    } else {
+        &url[prefix.len()..]
+    };
+    let domain = host
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .next()?;
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2
+        || labels.iter().any(|l| l.is_empty())
+        || labels[labels.len() - 2..].iter().any(|l| l.contains('_'))
+    {
+        return None;
+    }
+    let href = if prefix == "www." {
+        Url::parse(&format!("http://{}", url))
+    } else {
+        Url::parse(url)
+    };
+    Some((end, href.ok()?))
+}
+
+/// How `l` is written as an autolink, if it is one its text can stand for.
+fn autolink_text(l: &Link) -> Option<String> {
+    let bare = |s: &str| bare_url(s).is_some_and(|(end, _)| end == s.len());
+    match &l.items[..] {
+        [Item::Def(s, _)] if l.auto && l.bare && bare(s) => Some(s.to_string()),
+        [Item::Def(s, _)] if l.auto && autolink_target(s).is_some() => Some(format!("<{}>", s)),
+        [Item::Def(s, _)] if l.auto && bare(s) => Some(s.to_string()),
+        _ => None,
+    }
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub struct Heading {
     pub(crate) level: HeadingLvl,
-    pub(crate) content: String,
+    pub(crate) items: Box<[Item]>,
+    pub(crate) extensions: Extensions,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Heading { level, items });
+
+impl Heading {
+    pub fn level(&self) -> HeadingLvl {
+        self.level
+    }
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
 impl Element for Heading {
-    fn parse(iter: &mut Iter<crate::ParseToken>) -> Result<Self, crate::ParseError> {
-        if let Some(t) = iter.next() {
-            return match t {
-                ParseToken::RepeatSpecial('#', n) => {
-                    let tok = if let Some(s) = iter.next() {
-                        s
-                    } else {
-                        return Err(crate::ParseError::UnexpectedEnd);
-                    };
-                    let level = if *n > 6 {
-                        return Err(crate::ParseError::UnexpectedChar('#'));
-                    } else {
-                        HeadingLvl::iterate(*n)
-                    };
-                    match tok {
-                        ParseToken::RepeatSpecial(' ', _) => {
-                            let t = if let Some(t) = iter.next() {
-                                t
-                            } else {
-                                return Err(crate::ParseError::UnexpectedEnd);
-                            };
-                            match t {
-                                ParseToken::RepeatSpecial(c, _) => {
-                                    Err(crate::ParseError::UnexpectedChar(*c))
-                                }
-                                ParseToken::Number(_, _) => {
-                                    Err(crate::ParseError::UnexpectedString(t.to_string()))
-                                }
-                                ParseToken::String(s) => Ok(Heading {
-                                    level,
-                                    content: s.clone(),
-                                }),
-                            }
-                        }
-                        ParseToken::RepeatSpecial(c, _) => {
-                            Err(crate::ParseError::UnexpectedChar(*c))
-                        }
-                        ParseToken::Number(_, _) => {
-                            Err(crate::ParseError::UnexpectedString(t.to_string()))
-                        }
-                        ParseToken::String(s) => Ok(Heading {
-                            level,
-                            content: s.clone(),
-                        }),
-                    }
-                }
-                ParseToken::RepeatSpecial(c, _) => Err(crate::ParseError::UnexpectedChar(*c)),
-                ParseToken::String(s) => {
-                    crate::token_expect!(iter, '\n', 1);
-                    let t = if let Some(t) = iter.next() {
-                        t
-                    } else {
-                        return Err(crate::ParseError::UnexpectedEnd);
-                    };
-                    return match t {
-                        ParseToken::RepeatSpecial('=', _) => Ok(Heading {
-                            level: HeadingLvl::Level1,
-                            content: s.to_owned(),
-                        }),
-                        ParseToken::RepeatSpecial('-', _) => Ok(Heading {
-                            level: HeadingLvl::Level2,
-                            content: s.to_owned(),
-                        }),
-                        ParseToken::RepeatSpecial(c, _) => {
-                            Err(crate::ParseError::UnexpectedChar(*c))
-                        }
-                        ParseToken::String(s) => {
-                            Err(crate::ParseError::UnexpectedString(s.to_owned()))
-                        }
-                        ParseToken::Number(p, n) => {
-                            Err(crate::ParseError::UnexpectedNumber(*p, *n))
-                        }
-                    };
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        Self::parse_with(iter, &mut crate::Context::default())
+    }
+    fn parse_with(
+        iter: &mut Iter<Token>,
+        cx: &mut crate::Context,
+    ) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        let atx = matches!(
+            crate::kind(before, 1),
+            None | Some(ParseToken::RepeatSpecial(' ' | '\n' | '\r', _))
+        );
+        if let Some(ParseToken::RepeatSpecial('#', n)) = crate::kind(before, 0).filter(|_| atx) {
+            if *n > 6 {
+                return Err(crate::unexpected(&before[0]).with_expected(&["at most six `#`"]));
+            }
+            iter.next();
+            let line = iter.as_slice();
+            crate::take_line(iter);
+            let mut content = trim_end(&line[..line.len() - iter.as_slice().len()]);
+            if let [rest @ .., last] = content {
+                let closing = matches!(last.kind, ParseToken::RepeatSpecial('#', _));
+                let spaced = match rest.last().map(|t| &t.kind) {
+                    None | Some(ParseToken::RepeatSpecial(' ' | '\t', _)) => true,
+                    Some(ParseToken::String(s)) => s.ends_with(' '),
+                    _ => false,
+                };
+                if closing && spaced {
+                    content = trim_end(rest);
                 }
-                ParseToken::Number(_, _) => Err(crate::ParseError::UnexpectedString(t.to_string())),
-            };
+            }
+            return Ok(Heading {
+                level: HeadingLvl::iterate(*n),
+                items: parse_inline(content, cx).into_boxed_slice(),
+                extensions: cx.extensions,
+                span: crate::consumed(before, iter),
+            });
         }
-        return Err(crate::ParseError::UnexpectedEnd);
+        loop {
+            if iter.as_slice().is_empty() {
+                return Err(crate::ParseErrorKind::UnexpectedEnd.into());
+            }
+            let (_, breaks) = crate::take_line(iter);
+            if breaks > 1 {
+                return Err(crate::ParseErrorKind::UnexpectedChar('\n').into());
+            }
+            if let Some((level, n)) = setext_underline(iter.as_slice()) {
+                let content = trim_end(&before[..before.len() - iter.as_slice().len()]);
+                let items = parse_inline(content, cx);
+                iter.nth(n - 1);
+                return Ok(Heading {
+                    level,
+                    items: items.into_boxed_slice(),
+                    extensions: cx.extensions,
+                    span: crate::consumed(before, iter),
+                });
+            }
+        }
+    }
+}
+
+/// Drops the line breaks and spaces at the end of `tokens`.
+fn trim_end(mut tokens: &[Token]) -> &[Token] {
+    while let [rest @ .., last] = tokens {
+        match last.kind {
+            ParseToken::RepeatSpecial('\n' | '\r' | ' ' | '\t', _) => tokens = rest,
+            _ => break,
+        }
+    }
+    tokens
+}
+
+/// Returns the level of the setext underline (`===` or `---`) at the start of
+/// `tokens` together with the number of tokens it spans, if the line holds
+/// nothing else.
+pub(crate) fn setext_underline(tokens: &[Token]) -> Option<(HeadingLvl, usize)> {
+    let indent = match crate::kind(tokens, 0) {
+        Some(ParseToken::RepeatSpecial(' ', n)) if *n <= 3 => 1,
+        _ => 0,
+    };
+    let level = match crate::kind(tokens, indent) {
+        Some(ParseToken::RepeatSpecial('=', _)) => HeadingLvl::Level1,
+        Some(ParseToken::RepeatSpecial('-', _)) => HeadingLvl::Level2,
+        _ => return None,
+    };
+    let mut n = indent + 1;
+    if let Some(ParseToken::RepeatSpecial(' ', _)) = crate::kind(tokens, n) {
+        n += 1;
+    }
+    match crate::kind(tokens, n) {
+        None | Some(ParseToken::RepeatSpecial('\n', _)) => Some((level, n)),
+        _ => None,
     }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Heading {
+    /// Headings spanning several lines are rendered setext style, which only
+    /// has the first two levels; deeper ones have their lines joined instead.
     fn to_string(&self) -> String {
-        let mut content = "#".repeat(self.level.into());
+        let level: usize = self.level.into();
+        let lines = render(&self.items, true, self.extensions);
+        if lines.contains('\n') && level <= 2 {
+            let underline = if level == 1 { "===" } else { "---" };
+            return format!("{lines}\n{underline}");
+        }
+        let items: Vec<Item> = self
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Break(s) | Item::HardBreak(_, s) => Item::Def(" ".into(), *s),
+                _ => item.clone(),
+            })
+            .collect();
+        let mut content = "#".repeat(level);
         content.push(' ');
-        content.push_str(&self.content);
+        let text = render(&items, false, self.extensions);
+        let body = text.trim_end_matches('#');
+        content.push_str(body);
+        if body.len() < text.len() && (body.is_empty() || body.ends_with([' ', '\t'])) {
+            content.push('\\');
+        }
+        content.push_str(&text[body.len()..]);
         content
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HeadingLvl {
+    #[default]
     Level1,
     Level2,
     Level3,
@@ -124,9 +1281,9 @@ impl HeadingLvl {
         lvl
     }
     pub fn iterate_self(self, n: usize) -> Self {
-        let m: usize = (n + <HeadingLvl as Into<usize>>::into(self)) % 6;
+        let m: usize = (n + <HeadingLvl as Into<usize>>::into(self) - 1) % 6;
         let mut lvl = HeadingLvl::Level1;
-        while m > 0 {
+        for _ in 0..m {
             lvl = lvl.increment();
         }
         lvl
@@ -155,6 +1312,7 @@ impl HeadingLvl {
 
 macro_rules! into_headinglvl {
     ($num:ident) => {
+        #[allow(clippy::from_over_into)]
         impl Into<$num> for HeadingLvl {
             fn into(self) -> $num {
                 match self {
@@ -172,6 +1330,7 @@ macro_rules! into_headinglvl {
 
 macro_rules! into_headinglvlf {
     ($num:ident) => {
+        #[allow(clippy::from_over_into)]
         impl Into<$num> for HeadingLvl {
             fn into(self) -> $num {
                 match self {
@@ -202,396 +1361,940 @@ into_headinglvl!(isize);
 into_headinglvlf!(f32);
 into_headinglvlf!(f64);
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub enum Quote<'a> {
-    Nested(&'a Quote<'a>),
-    Items(Box<[Item]>),
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LinkSource {
     Url(Url),
+    Relative(RelativePath),
     Ref(Box<str>),
+    #[default]
     None,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// A link destination relative to the document, such as `./setup.md#install`
+/// or just the fragment `#usage`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RelativePath {
+    path: Box<str>,
+    query: Option<Box<str>>,
+    fragment: Option<Box<str>>,
+}
+
+impl RelativePath {
+    /// Splits `s` into its path, the query after `?` and the fragment after
+    /// `#`.
+    pub fn new(s: &str) -> Self {
+        let (rest, fragment) = match s.split_once('#') {
+            Some((rest, f)) => (rest, Some(f.into())),
+            None => (s, None),
+        };
+        let (path, query) = match rest.split_once('?') {
+            Some((path, q)) => (path, Some(q.into())),
+            None => (rest, None),
+        };
+        Self {
+            path: path.into(),
+            query,
+            fragment,
+        }
+    }
+    /// The path, empty for a link within the document.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for RelativePath {
+    fn to_string(&self) -> String {
+        let mut s = self.path.to_string();
+        if let Some(q) = &self.query {
+            s.push('?');
+            s.push_str(q);
+        }
+        if let Some(f) = &self.fragment {
+            s.push('#');
+            s.push_str(f);
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Link {
-    pub(crate) name: Box<str>,
+    pub(crate) items: Box<[Item]>,
     pub(crate) src: LinkSource,
+    pub(crate) title: Option<Box<str>>,
     pub(crate) img: bool,
+    pub(crate) auto: bool,
+    pub(crate) bare: bool,
+    pub(crate) style: RefStyle,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Link {
+    items,
+    src,
+    title,
+    img,
+    auto,
+    bare,
+    style
+});
+
+/// How a link to a reference is written: in full as `[text][label]`,
+/// collapsed as `[label][]`, or as the shortcut `[label]`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RefStyle {
+    #[default]
+    Full,
+    Collapsed,
+    Shortcut,
+}
+
+impl Link {
+    /// The inline content of the link text, or of the image description.
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+    /// The link text without any inline markup.
+    pub fn name(&self) -> String {
+        self.items.iter().map(Item::text).collect()
+    }
+    pub fn src(&self) -> &LinkSource {
+        &self.src
+    }
+    /// How the link is written when its source is a reference.
+    pub fn style(&self) -> RefStyle {
+        self.style
+    }
+    /// The title shown for the link or image, as in `[a](b "Title")`.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+    /// Whether the link is written as its own destination, between `<` and
+    /// `>` or as a bare URL.
+    pub fn is_autolink(&self) -> bool {
+        self.auto
+    }
+    /// Whether the link is a bare URL rather than one between `<` and `>`.
+    pub fn is_bare(&self) -> bool {
+        self.bare
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Item> for Link {
+    fn into(self) -> Item {
+        Item::Link(self)
+    }
+}
+
+/// The way a hard line break is written: two or more spaces at the end of the
+/// line, or a backslash.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HardBreak {
+    #[default]
+    Spaces,
+    Backslash,
+}
+
+#[allow(clippy::to_string_trait_impl)]
+impl ToString for HardBreak {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Spaces => String::from("  \n"),
+            Self::Backslash => String::from("\\\n"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+    Bold(Box<[Item]>, Span),
+    BoldItalic(Box<[Item]>, Span),
+    Def(Box<str>, Span),
+    Italic(Box<[Item]>, Span),
+    Strikethrough(Box<[Item]>, Span),
+    Highlight(Box<[Item]>, Span),
+    Superscript(Box<[Item]>, Span),
+    Subscript(Box<[Item]>, Span),
+    Link(Link),
+    Code(Box<str>, Span),
+    /// A soft line break, ending a line in the middle of a paragraph.
+    Break(Span),
+    /// A line break that is kept when the text is displayed.
+    HardBreak(HardBreak, Span),
+    /// Raw HTML, such as a tag or a comment, kept exactly as it was written.
+    Html(Box<str>, Span),
+}
+
+/// An [`Item`] without its span, which items are compared by.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum ItemContent<'a> {
+    Bold(&'a [Item]),
+    BoldItalic(&'a [Item]),
+    Def(&'a str),
+    Italic(&'a [Item]),
+    Strikethrough(&'a [Item]),
+    Highlight(&'a [Item]),
+    Superscript(&'a [Item]),
+    Subscript(&'a [Item]),
+    Link(&'a Link),
+    Code(&'a str),
+    Break,
+    HardBreak(HardBreak),
+    Html(&'a str),
+}
+
+impl Item {
+    fn content(&self) -> ItemContent<'_> {
+        match self {
+            Self::Bold(c, _) => ItemContent::Bold(c),
+            Self::BoldItalic(c, _) => ItemContent::BoldItalic(c),
+            Self::Def(s, _) => ItemContent::Def(s),
+            Self::Italic(c, _) => ItemContent::Italic(c),
+            Self::Strikethrough(c, _) => ItemContent::Strikethrough(c),
+            Self::Highlight(c, _) => ItemContent::Highlight(c),
+            Self::Superscript(c, _) => ItemContent::Superscript(c),
+            Self::Subscript(c, _) => ItemContent::Subscript(c),
+            Self::Link(l) => ItemContent::Link(l),
+            Self::Code(s, _) => ItemContent::Code(s),
+            Self::Break(_) => ItemContent::Break,
+            Self::HardBreak(b, _) => ItemContent::HardBreak(*b),
+            Self::Html(s, _) => ItemContent::Html(s),
+        }
+    }
 }
 
-impl Into<Item> for Link {
-    fn into(self) -> Item {
-        Item::Link(self)
+impl PartialEq for Item {
+    fn eq(&self, other: &Self) -> bool {
+        self.content() == other.content()
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub enum Item {
-    Bold(Box<str>),
-    BoldItalic(Box<str>),
-    Def(Box<str>),
-    Italic(Box<str>),
-    Link(Link),
+impl Eq for Item {}
+
+impl PartialOrd for Item {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Item {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.content().cmp(&other.content())
+    }
 }
 
 impl Item {
     pub fn asterick(&mut self) {
         *self = match self {
-            Self::Italic(c) => Self::Bold(c.clone()),
-            Self::Bold(c) => Self::BoldItalic(c.clone()),
-            Self::BoldItalic(c) => Self::Bold(c.clone()),
+            Self::Italic(c, s) => Self::Bold(c.clone(), *s),
+            Self::Bold(c, s) => Self::BoldItalic(c.clone(), *s),
+            Self::BoldItalic(c, s) => Self::Bold(c.clone(), *s),
             _ => self.clone(),
         }
     }
     pub fn asterick_cons(self) -> Self {
         match self {
-            Self::Italic(c) => Self::Bold(c),
-            Self::Bold(c) => Self::BoldItalic(c),
-            Self::BoldItalic(c) => Self::Bold(c),
+            Self::Italic(c, s) => Self::Bold(c, s),
+            Self::Bold(c, s) => Self::BoldItalic(c, s),
+            Self::BoldItalic(c, s) => Self::Bold(c, s),
             _ => self,
         }
     }
     pub fn is_empty(&self) -> bool {
         match self {
-            Self::Italic(c) => c.is_empty(),
-            Self::Bold(c) => c.is_empty(),
-            Self::BoldItalic(c) => c.is_empty(),
+            Self::Italic(c, _) => c.is_empty(),
+            Self::Bold(c, _) => c.is_empty(),
+            Self::BoldItalic(c, _) => c.is_empty(),
+            Self::Strikethrough(c, _)
+            | Self::Highlight(c, _)
+            | Self::Superscript(c, _)
+            | Self::Subscript(c, _) => c.is_empty(),
+            Self::Code(c, _) => c.is_empty(),
             _ => false,
         }
     }
+    /// The inline content nested in emphasis, the other formatting kinds or a
+    /// link, empty for anything else.
+    pub fn children(&self) -> &[Item] {
+        match self {
+            Self::Bold(c, _)
+            | Self::BoldItalic(c, _)
+            | Self::Italic(c, _)
+            | Self::Strikethrough(c, _)
+            | Self::Highlight(c, _)
+            | Self::Superscript(c, _)
+            | Self::Subscript(c, _) => c,
+            Self::Link(l) => &l.items,
+            _ => &[],
+        }
+    }
+    /// The text of the item without any inline markup.
+    pub fn text(&self) -> String {
+        match self {
+            Self::Def(s, _) | Self::Code(s, _) => s.to_string(),
+            Self::Break(_) | Self::HardBreak(..) => String::from("\n"),
+            Self::Html(..) => String::new(),
+            _ => self.children().iter().map(Item::text).collect(),
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Bold(_, s)
+            | Self::BoldItalic(_, s)
+            | Self::Def(_, s)
+            | Self::Italic(_, s)
+            | Self::Strikethrough(_, s)
+            | Self::Highlight(_, s)
+            | Self::Superscript(_, s)
+            | Self::Subscript(_, s)
+            | Self::Code(_, s)
+            | Self::Break(s)
+            | Self::HardBreak(_, s)
+            | Self::Html(_, s) => *s,
+            Self::Link(l) => l.span,
+        }
+    }
+    /// Whether the item is a soft or hard line break.
+    pub fn is_break(&self) -> bool {
+        matches!(self, Self::Break(_) | Self::HardBreak(..))
+    }
+    /// The tag raw HTML starts with, if it is one.
+    pub fn tag(&self) -> Option<crate::html::Tag> {
+        match self {
+            Self::Html(s, _) => crate::html::Tag::parse(s),
+            _ => None,
+        }
+    }
+    fn set_span(&mut self, span: Span) {
+        match self {
+            Self::Bold(_, s)
+            | Self::BoldItalic(_, s)
+            | Self::Def(_, s)
+            | Self::Italic(_, s)
+            | Self::Strikethrough(_, s)
+            | Self::Highlight(_, s)
+            | Self::Superscript(_, s)
+            | Self::Subscript(_, s)
+            | Self::Code(_, s)
+            | Self::Break(s)
+            | Self::HardBreak(_, s)
+            | Self::Html(_, s) => *s = span,
+            Self::Link(l) => l.span = span,
+        }
+    }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Item {
     fn to_string(&self) -> String {
-        return match self {
-            Self::Def(s) => s.to_string(),
-            Self::Italic(s) => format!("*{}*", s),
-            Self::Bold(s) => format!("**{}**", s),
-            Self::BoldItalic(s) => format!("***{}***", s),
-            Self::Link(l) => format!(
-                "{}[{}]({})",
-                if l.img { "!" } else { "" },
-                l.name,
-                l.src.to_string()
-            ),
-        };
+        self.render(Extensions::default())
+    }
+}
+
+impl Item {
+    /// Renders the item, escaping its text against `extensions`.
+    fn render(&self, extensions: Extensions) -> String {
+        let render = |c| render(c, false, extensions);
+        match self {
+            Self::Def(s, _) => escape(s, false, extensions),
+            Self::Italic(c, _) => format!("*{}*", render(c)),
+            Self::Bold(c, _) => format!("**{}**", render(c)),
+            Self::BoldItalic(c, _) => format!("***{}***", render(c)),
+            Self::Strikethrough(c, _) => format!("~~{}~~", render(c)),
+            Self::Highlight(c, _) => format!("=={}==", render(c)),
+            Self::Superscript(c, _) => format!("^{}^", render(c)),
+            Self::Subscript(c, _) => format!("~{}~", render(c)),
+            Self::Link(l) if autolink_text(l).is_some() => autolink_text(l).unwrap(),
+            Self::Link(l) => {
+                let text = render(&l.items);
+                let tail = ref_tail(l, &text).unwrap_or_else(|| format!("({})", link_tail_text(l)));
+                format!("{}[{}]{}", if l.img { "!" } else { "" }, text, tail)
+            }
+            Self::Code(s, _) => {
+                let fence = "`".repeat(fence_len(s));
+                let spaced = s.starts_with(' ') && s.ends_with(' ') && !s.trim().is_empty();
+                let pad = if s.starts_with('`') || s.ends_with('`') || spaced {
+                    " "
+                } else {
+                    ""
+                };
+                format!("{}{}{}{}{}", fence, pad, s, pad, fence)
+            }
+            Self::Break(_) => String::from("\n"),
+            Self::HardBreak(b, _) => b.to_string(),
+            Self::Html(s, _) => s.to_string(),
+        }
     }
 }
 
+/// The shortest run of backticks that does not occur in `s`, for fencing it
+/// as a code span.
+fn fence_len(s: &str) -> usize {
+    let runs: Vec<usize> = s
+        .split(|c| c != '`')
+        .map(|r| r.len())
+        .filter(|n| *n > 0)
+        .collect();
+    (1..).find(|n| !runs.contains(n)).unwrap()
+}
+
 impl super::Element for Item {
-    fn parse(iter: &mut Iter<ParseToken>) -> Result<Self, crate::ParseError> {
-        while let Some(first_tok) = iter.next() {
-            match first_tok {
-                ParseToken::RepeatSpecial('!', 1) => return process_link_item(iter, true),
-                ParseToken::RepeatSpecial('[', 1) => return process_link_item(iter, false),
-                ParseToken::RepeatSpecial('*', n) => {
-                    let src = if let Some(tok) = iter.next() {
-                        match tok {
-                            ParseToken::RepeatSpecial(c, _) => {
-                                return Err(crate::ParseError::UnexpectedChar(*c))
-                            }
-                            ParseToken::String(s) => s.to_owned(),
-                            ParseToken::Number(_, _) => {
-                                return Err(crate::ParseError::UnexpectedString(tok.to_string()))
-                            }
-                        }
-                    } else {
-                        return Err(crate::ParseError::UnexpectedEnd);
-                    };
-                    if let Some(tok) = iter.next() {
-                        match tok {
-                            ParseToken::RepeatSpecial('*', m) => {
-                                if m < n {
-                                    return Err(crate::ParseError::UnexpectedEnd);
-                                } else {
-                                    let mut i = 1;
-                                    let mut val = Self::Italic(src.into_boxed_str());
-                                    while i < *n {
-                                        i += 1;
-                                        val.asterick()
-                                    }
-                                    return Ok(val);
-                                }
-                            }
-                            ParseToken::RepeatSpecial(c, _) => {
-                                return Err(crate::ParseError::UnexpectedChar(*c))
-                            }
-                            ParseToken::String(s) => {
-                                return Err(crate::ParseError::UnexpectedString(s.clone()))
-                            }
-                            ParseToken::Number(_, _) => {
-                                return Err(crate::ParseError::UnexpectedString(tok.to_string()))
-                            }
-                        }
-                    }
-                }
-                ParseToken::String(s) => {
-                    let mut src = s.clone();
-                    while let Some(tok) = iter.next() {
-                        match tok {
-                            ParseToken::RepeatSpecial(c, n) => {
-                                src.push_str(&c.to_string().repeat(*n))
-                            }
-                            ParseToken::String(s) => src.push_str(s),
-                            ParseToken::Number(_, _) => src.push_str(&tok.to_string()),
-                        }
-                    }
-                }
-                ParseToken::RepeatSpecial(' ', _) => continue,
-                ParseToken::Number(_, _) => {
-                    return Err(crate::ParseError::UnexpectedString(first_tok.to_string()))
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        let mut item = parse_item(iter)?;
+        item.set_span(crate::consumed(before, iter));
+        Ok(item)
+    }
+}
+
+fn parse_item(iter: &mut Iter<Token>) -> Result<Item, crate::ParseError> {
+    while let Some(ParseToken::RepeatSpecial(' ', _)) = crate::kind(iter.as_slice(), 0) {
+        iter.next();
+    }
+    let tokens = iter.as_slice();
+    let first = match tokens.first() {
+        Some(t) => t,
+        None => return Err(crate::ParseErrorKind::EmptyDocument.into()),
+    };
+    let unclosed = |c: char, n: usize| {
+        crate::ParseError::from(crate::ParseErrorKind::UnexpectedEnd)
+            .with_expected(&[&format!("`{}`", c.to_string().repeat(n))])
+    };
+    let (item, len) = match &first.kind {
+        ParseToken::RepeatSpecial('!', 1) => {
+            iter.next();
+            return process_link_item(iter, true, &mut crate::Context::default());
+        }
+        ParseToken::RepeatSpecial('[', 1) => {
+            iter.next();
+            return process_link_item(iter, false, &mut crate::Context::default());
+        }
+        ParseToken::RepeatSpecial(c @ ('*' | '_'), n) => {
+            match parse_inline(tokens, &mut crate::Context::default())
+                .into_iter()
+                .next()
+            {
+                Some(item @ (Item::Bold(..) | Item::BoldItalic(..) | Item::Italic(..))) => {
+                    let end = item.span().end().offset();
+                    let len = tokens
+                        .iter()
+                        .take_while(|t| t.span.end().offset() <= end)
+                        .count();
+                    (item, len)
                 }
-                _ => {}
+                _ => return Err(unclosed(*c, *n)),
             }
         }
-        return Err(crate::ParseError::EmptyDocument);
-    }
+        ParseToken::RepeatSpecial('`', n) => {
+            code_span(tokens, *n).ok_or_else(|| unclosed('`', *n))?
+        }
+        ParseToken::RepeatSpecial('<', 1) => {
+            let mut tokens = tokens.to_vec();
+            autolink(&mut tokens, 0)
+                .or_else(|| inline_html(&mut tokens, 0, &mut Vec::new()))
+                .ok_or_else(|| crate::unexpected(first))?
+        }
+        ParseToken::String(_) => {
+            let src: String = iter.map(|t| t.to_string()).collect();
+            return Ok(Item::Def(src.into_boxed_str(), Span::default()));
+        }
+        _ => return Err(crate::unexpected(first)),
+    };
+    iter.nth(len - 1);
+    Ok(item)
 }
 
 #[inline]
-fn process_link_item(iter: &mut Iter<ParseToken>, img: bool) -> Result<Item, crate::ParseError> {
+fn process_link_item(
+    iter: &mut Iter<Token>,
+    img: bool,
+    cx: &mut crate::Context,
+) -> Result<Item, crate::ParseError> {
     if img {
-        let tok = if let Some(n) = iter.next() {
-            n
-        } else {
-            return Err(crate::ParseError::UnexpectedEnd);
-        };
-        match tok {
-            ParseToken::RepeatSpecial('[', 1) => {}
-            ParseToken::String(s) => return Err(crate::ParseError::UnexpectedString(s.to_owned())),
-            ParseToken::RepeatSpecial(c, _) => return Err(crate::ParseError::UnexpectedChar(*c)),
-            ParseToken::Number(_, _) => {
-                return Err(crate::ParseError::UnexpectedString(tok.to_string()))
+        crate::token_expect!(iter, '[', 1);
+    }
+    let tokens = iter.as_slice();
+    let end = match link_text_end(tokens) {
+        Some(0) => return Err(crate::unexpected(&tokens[0]).with_expected(&["a link name"])),
+        Some(end) => end,
+        None => {
+            iter.by_ref().for_each(drop);
+            return Err(
+                crate::ParseError::from(crate::ParseErrorKind::UnexpectedEnd)
+                    .with_expected(&["`]`"]),
+            );
+        }
+    };
+    let extensions = cx.extensions;
+    cx.extensions = extensions.extended_autolinks(false);
+    let items = parse_link_text(&tokens[..end], cx);
+    cx.extensions = extensions;
+    iter.nth(end);
+    let text: String = tokens[..end].iter().map(Token::to_string).collect();
+    let shortcut = cx.references.contains(&label_key(&text));
+    let (src, title, style) = match crate::kind(iter.as_slice(), 0) {
+        Some(ParseToken::RepeatSpecial('(', 1)) => {
+            let mut inline = iter.clone();
+            inline.next();
+            match link_tail(&mut inline) {
+                Ok((dest, title)) => {
+                    *iter = inline;
+                    (link_source(dest)?, title, RefStyle::Full)
+                }
+                Err(_) if shortcut => (LinkSource::Ref(text.into()), None, RefStyle::Shortcut),
+                Err(e) => return Err(e),
             }
         }
-    }
-    let name = if let Some(tok) = iter.next() {
-        match tok {
-            ParseToken::RepeatSpecial(c, _) => return Err(crate::ParseError::UnexpectedChar(*c)),
-            ParseToken::String(s) => s.to_owned().into_boxed_str(),
-            ParseToken::Number(_, _) => {
-                return Err(crate::ParseError::UnexpectedString(tok.to_string()))
+        Some(ParseToken::RepeatSpecial('[', 1)) if ref_label(&iter.as_slice()[1..]).is_some() => {
+            let (label, len) = ref_label(&iter.as_slice()[1..]).unwrap();
+            iter.nth(len);
+            if label.is_empty() {
+                (LinkSource::Ref(text.into()), None, RefStyle::Collapsed)
+            } else {
+                (LinkSource::Ref(label.into()), None, RefStyle::Full)
             }
         }
-    } else {
-        return Err(crate::ParseError::UnexpectedEnd);
+        _ if shortcut => (LinkSource::Ref(text.into()), None, RefStyle::Shortcut),
+        _ => {
+            return Err(crate::unexpected_or_end(iter.next()).with_expected(&["`(`", "`[`"]));
+        }
     };
-    crate::token_expect!(iter, ']', 1);
-    crate::token_expect!(iter, '(', 1);
-    let mut src_str = String::new();
-    let res = loop {
-        let tok = if let Some(tok) = iter.next() {
-            tok
-        } else {
-            break false;
+    Ok(Item::Link(Link {
+        items: items.into_boxed_slice(),
+        src,
+        title: title.map(String::into_boxed_str),
+        img,
+        auto: false,
+        bare: false,
+        style,
+        span: Span::default(),
+    }))
+}
+
+/// The source of an inline link or image to `src_str`, which is relative to
+/// the document unless it is an absolute URL.
+fn link_source(src_str: String) -> Result<LinkSource, crate::ParseError> {
+    if src_str.is_empty() {
+        return Ok(LinkSource::None);
+    }
+    match Url::parse(&src_str) {
+        Ok(u) => Ok(LinkSource::Url(u)),
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            Ok(LinkSource::Relative(RelativePath::new(&src_str)))
+        }
+        Err(e) => Err(crate::ParseErrorKind::InvalidUrl(e).into()),
+    }
+}
+
+/// Reads the label of a full or collapsed reference link from `tokens`, which
+/// follow its opening `[`, returning it with the number of tokens up to and
+/// including its closing `]`. The label of a collapsed reference is empty.
+fn ref_label(tokens: &[Token]) -> Option<(String, usize)> {
+    let mut label = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if escapes(tokens, i) {
+            label.push_str(&tokens[i].to_string());
+            label.push_str(&tokens[i + 1].to_string());
+            i += 2;
+            continue;
+        }
+        match &tokens[i].kind {
+            ParseToken::RepeatSpecial(']', 1) => {
+                let valid =
+                    label.chars().count() <= 999 && (label.is_empty() || !label.trim().is_empty());
+                return valid.then_some((label, i + 1));
+            }
+            ParseToken::RepeatSpecial('[' | ']', _) => return None,
+            _ => label.push_str(&tokens[i].to_string()),
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Adds the links and images in `items` to `links`, nested ones included.
+pub(crate) fn collect_links(items: &[Item], links: &mut Vec<Link>) {
+    for item in items {
+        if let Item::Link(l) = item {
+            links.push(l.clone());
+        }
+        collect_links(item.children(), links);
+    }
+}
+
+/// Normalizes a reference label for matching it against others: case is
+/// folded and runs of whitespace are collapsed into single spaces.
+pub(crate) fn label_key(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Reads the destination and optional title of an inline link, up to and
+/// including the `)` closing them, with their escapes and entity references
+/// decoded.
+fn link_tail(iter: &mut Iter<Token>) -> Result<(String, Option<String>), crate::ParseError> {
+    // Only as much of the rest of the paragraph is read as the tail needs,
+    // doubling what is read while it runs up against the end of it.
+    let all = iter.as_slice();
+    let mut n = 64;
+    let (tokens, chars, target) = loop {
+        let tokens = &all[..n.min(all.len())];
+        let chars = chars_of(tokens);
+        let target = link_target(tokens, &chars);
+        let short = match &target {
+            Ok((_, _, i)) => *i >= chars.len(),
+            Err(e) => *e.kind() == crate::ParseErrorKind::UnexpectedEnd,
         };
-        match tok {
-            ParseToken::RepeatSpecial(')', 1) => break true,
-            ParseToken::RepeatSpecial(c, n) => src_str.push_str(&c.to_string().repeat(*n)),
-            ParseToken::String(s) => src_str.push_str(s),
-            ParseToken::Number(_, _) => {
-                return Err(crate::ParseError::UnexpectedString(tok.to_string()))
+        if !short || tokens.len() == all.len() {
+            break (tokens, chars, target);
+        }
+        n *= 2;
+    };
+    let (dest, title, i) = target?;
+    if chars.get(i) != Some(&')') {
+        return Err(fail_at(tokens, &chars, i, "`)`"));
+    }
+    take_chars_from(iter, i + 1).map_err(|_| fail_at(tokens, &chars, i + 1, "end of input"))?;
+    Ok((dest, title))
+}
+
+#[inline]
+fn chars_of(tokens: &[Token]) -> Vec<char> {
+    tokens
+        .iter()
+        .flat_map(|t| t.to_string().chars().collect::<Vec<_>>())
+        .collect()
+}
+
+/// The error for the character at `i` in `chars`, the text of `tokens`, which
+/// should have been `expected`.
+fn fail_at(tokens: &[Token], chars: &[char], i: usize, expected: &str) -> crate::ParseError {
+    let start = position_in(tokens, i);
+    let err = match chars.get(i) {
+        Some(c) => {
+            let mut end = start;
+            end.advance(&c.to_string());
+            crate::ParseError::from(crate::ParseErrorKind::UnexpectedChar(*c))
+                .with_span(Span::new(start, end))
+        }
+        None => crate::ParseError::from(crate::ParseErrorKind::UnexpectedEnd)
+            .with_span(Span::new(start, start)),
+    };
+    err.with_expected(&[expected])
+}
+
+/// Takes the tokens making up the first `n` characters off `iter`, failing if
+/// the last of them does not end there.
+fn take_chars_from(iter: &mut Iter<Token>, mut n: usize) -> Result<(), ()> {
+    while n > 0 {
+        let len = iter.next().ok_or(())?.to_string().chars().count();
+        n = n.checked_sub(len).ok_or(())?;
+    }
+    Ok(())
+}
+
+/// Reads a link destination and an optional title from `chars`, the text of
+/// `tokens`, as they follow the `(` of an inline link or the `:` of a
+/// reference definition. Returns them with their escapes and entity
+/// references decoded, along with where the spaces after them end. The
+/// destination may be written between `<` and `>` to hold spaces, and
+/// otherwise holds parentheses only if they are balanced.
+fn link_target(
+    tokens: &[Token],
+    chars: &[char],
+) -> Result<(String, Option<String>, usize), crate::ParseError> {
+    let fail = |i: usize, expected: &str| fail_at(tokens, chars, i, expected);
+    let escaped =
+        |i: usize| chars[i] == '\\' && chars.get(i + 1).is_some_and(char::is_ascii_punctuation);
+    let start = skip_space(chars, 0);
+    let mut i = start;
+    let dest = if chars.get(i) == Some(&'<') {
+        loop {
+            i += 1;
+            match chars.get(i) {
+                Some('>') => break,
+                None | Some('<' | '\n') => return Err(fail(i, "`>`")),
+                _ if escaped(i) => i += 1,
+                _ => {}
+            }
+        }
+        i += 1;
+        &chars[start + 1..i - 1]
+    } else {
+        let mut depth = 0;
+        while let Some(c) = chars.get(i) {
+            match c {
+                _ if escaped(i) => i += 1,
+                '(' => depth += 1,
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                c if *c == ' ' || c.is_ascii_control() => break,
+                _ => {}
             }
+            i += 1;
         }
+        &chars[start..i]
     };
-    if !res {
-        return Err(crate::ParseError::UnexpectedEnd);
-    } else if let Some(t) = iter.next() {
-        return Err(match t {
-            ParseToken::RepeatSpecial(c, _) => crate::ParseError::UnexpectedChar(*c),
-            ParseToken::String(s) => crate::ParseError::UnexpectedString(s.to_owned()),
-            ParseToken::Number(_, _) => {
-                return Err(crate::ParseError::UnexpectedString(t.to_string()))
+    let dest = decode(&dest.iter().collect::<String>());
+    let mut title = None;
+    let gap = skip_space(chars, i);
+    if let Some(open @ ('"' | '\'' | '(')) = chars.get(gap).filter(|_| gap > i) {
+        let close = if *open == '(' { ')' } else { *open };
+        let mut j = gap;
+        loop {
+            j += 1;
+            match chars.get(j) {
+                Some(c) if *c == close => break,
+                Some('(') if *open == '(' => return Err(fail(j, "`)`")),
+                None => return Err(fail(j, &format!("`{}`", close))),
+                _ if escaped(j) => j += 1,
+                _ => {}
             }
-        });
+        }
+        title = Some(decode(&chars[gap + 1..j].iter().collect::<String>()));
+        i = j + 1;
     }
-    let u = url::Url::parse(&src_str);
-    let src = if let Some(e) = u.as_ref().err() {
-        if img {
-            return Err(crate::ParseError::InvalidUrl(e.to_owned()));
+    Ok((dest, title, skip_space(chars, i)))
+}
+
+/// Skips the spaces and tabs from `i` in `chars`, along with at most one line
+/// break among them.
+fn skip_space(chars: &[char], mut i: usize) -> usize {
+    let mut newline = false;
+    while let Some(c) = chars.get(i) {
+        match c {
+            ' ' | '\t' => {}
+            '\n' if !newline => newline = true,
+            _ => break,
         }
-        for c in src_str.chars() {
-            match c {
-                '0'..='9' => {}
-                'A'..='Z' => {}
-                'a'..='z' => {}
-                '?' => {}
-                '!' => {}
-                '.' => {}
-                ',' => {}
-                ';' => {}
-                ':' => {}
-                '\'' => {}
-                '"' => {}
-                _ => return Err(crate::ParseError::InvalidUrl(e.clone())),
-            }
-        }
-        LinkSource::Ref(src_str.into_boxed_str())
+        i += 1;
+    }
+    i
+}
+
+/// The position `n` characters into `tokens`.
+fn position_in(tokens: &[Token], mut n: usize) -> Position {
+    for t in tokens {
+        let text = t.to_string();
+        let len = text.chars().count();
+        if n < len {
+            let mut p = t.span.start();
+            p.advance(&text.chars().take(n).collect::<String>());
+            return p;
+        }
+        n -= len;
+    }
+    tokens.last().map_or(Position::START, |t| t.span.end())
+}
+
+/// What follows the link text `text` of `l` when it links to a reference. The
+/// label is left out where the text can stand for it.
+fn ref_tail(l: &Link, text: &str) -> Option<String> {
+    let label = match &l.src {
+        LinkSource::Ref(label) => label,
+        _ => return None,
+    };
+    Some(match l.style {
+        RefStyle::Collapsed if label_key(label) == label_key(text) => String::from("[]"),
+        RefStyle::Shortcut if label_key(label) == label_key(text) => String::new(),
+        _ => format!("[{}]", label),
+    })
+}
+
+/// What goes between the parentheses of the inline link `l`: its destination,
+/// between `<` and `>` where it could not be read back otherwise, and its
+/// title.
+fn link_tail_text(l: &Link) -> String {
+    let src = l.src.to_string();
+    let depth = src.chars().try_fold(0usize, |d, c| match c {
+        '(' => Some(d + 1),
+        ')' => d.checked_sub(1),
+        _ => Some(d),
+    });
+    let bracket = src.contains(|c: char| c == ' ' || c == '<' || c.is_ascii_control())
+        || depth != Some(0)
+        || (src.is_empty() && l.title.is_some());
+    let mut out = if bracket {
+        format!("<{}>", src.replace('<', "\\<").replace('>', "\\>"))
     } else {
-        LinkSource::Url(u.ok().unwrap())
+        src
     };
-    return Ok(Item::Link(Link { name, src, img }));
+    if let Some(title) = &l.title {
+        out.push_str(" \"");
+        for (i, c) in title.char_indices() {
+            if matches!(c, '"' | '\\') || (c == '&' && entity(&title[i..]).is_some()) {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('"');
+    }
+    out
+}
+
+/// The index of the `]` closing the link text at the start of `tokens`,
+/// passing over nested brackets, code spans and escaped characters.
+fn link_text_end(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 1;
+    let mut i = 0;
+    while i < tokens.len() {
+        if escapes(tokens, i) {
+            i += 2;
+            continue;
+        }
+        match &tokens[i].kind {
+            ParseToken::RepeatSpecial('[', n) => depth += n,
+            ParseToken::RepeatSpecial(']', n) if *n >= depth => return (*n == depth).then_some(i),
+            ParseToken::RepeatSpecial(']', n) => depth -= n,
+            ParseToken::RepeatSpecial('`', n) => {
+                if let Some((_, len)) = code_span(&tokens[i..], *n) {
+                    i += len;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone)]
 pub struct Reference {
     pub(crate) name: Box<str>,
     pub(crate) title: Box<str>,
-    pub(crate) href: Url,
+    pub(crate) href: LinkSource,
+    pub(crate) span: Span,
+}
+
+crate::impl_content_eq!(Reference { name, title, href });
+
+impl Reference {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The destination of the definition, an absolute URL or a path relative
+    /// to the document.
+    pub fn href(&self) -> &LinkSource {
+        &self.href
+    }
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    /// Whether `label` refers to this definition, comparing it to the name
+    /// regardless of case and runs of whitespace.
+    pub fn matches(&self, label: &str) -> bool {
+        label_key(&self.name) == label_key(label)
+    }
 }
 
 impl super::Element for Reference {
-    fn parse(iter: &mut Iter<ParseToken>) -> Result<Self, crate::ParseError> {
-        return if let Some(t) = iter.next() {
-            match t {
+    fn parse(iter: &mut Iter<Token>) -> Result<Self, crate::ParseError> {
+        let before = iter.as_slice();
+        if let Some(t) = iter.next() {
+            match &t.kind {
                 ParseToken::RepeatSpecial('[', 1) => {
                     let mut name = String::new();
-                    while let Some(t) = iter.next() {
-                        match t {
-                            ParseToken::String(s) => name.push_str(s),
-                            ParseToken::RepeatSpecial('!', n) => name.push_str(&"!".repeat(*n)),
-                            ParseToken::RepeatSpecial('?', n) => name.push_str(&"?".repeat(*n)),
-                            ParseToken::RepeatSpecial('.', n) => name.push_str(&".".repeat(*n)),
-                            ParseToken::RepeatSpecial(',', n) => name.push_str(&",".repeat(*n)),
-                            ParseToken::RepeatSpecial(';', n) => name.push_str(&";".repeat(*n)),
-                            ParseToken::RepeatSpecial(':', n) => name.push_str(&":".repeat(*n)),
-                            ParseToken::RepeatSpecial('\'', n) => name.push_str(&"'".repeat(*n)),
-                            ParseToken::RepeatSpecial('"', n) => name.push_str(&"\"".repeat(*n)),
-                            ParseToken::Number(_, _) => name.push_str(&t.to_string()),
+                    for t in iter.by_ref() {
+                        match &t.kind {
                             ParseToken::RepeatSpecial(']', 1) => {
                                 if name.is_empty() {
-                                    return Err(crate::ParseError::UnexpectedChar(']'));
+                                    return Err(
+                                        crate::unexpected(t).with_expected(&["a reference name"])
+                                    );
                                 }
                                 break;
                             }
-                            ParseToken::RepeatSpecial(c, _) => {
-                                return Err(crate::ParseError::UnexpectedChar(*c))
+                            ParseToken::RepeatSpecial('[' | ']' | '\n', _) => {
+                                return Err(crate::unexpected(t).with_expected(&["`]`"]))
                             }
+                            _ => name.push_str(&t.to_string()),
                         }
                     }
                     if name.is_empty() {
-                        return Err(crate::ParseError::UnexpectedEnd);
+                        return Err(
+                            crate::ParseError::from(crate::ParseErrorKind::UnexpectedEnd)
+                                .with_expected(&["a reference name"]),
+                        );
                     }
                     crate::token_expect!(iter, ':', 1);
-                    let t = crate::token_ignore_char!(iter, ' ');
-                    let (t, n) = crate::token_ignore_char!(iter, '<', 1, t);
-                    let href = &crate::token_combine_except!(
-                        t,
-                        iter,
-                        ParseToken::RepeatSpecial('>', 1),
-                        {
-                            if n == 0 {
-                                return Err(crate::ParseError::UnexpectedChar('>'));
-                            }
-                            break;
-                        },
-                        ParseToken::RepeatSpecial(' ', 1),
-                        {
-                            if n == 0 {
-                                return Err(crate::ParseError::UnexpectedChar(' '));
-                            }
-                            break;
-                        }
-                    );
-                    let url = {
-                        let url = url::Url::parse(&href);
-                        if let Some(e) = url.as_ref().err() {
-                            return Err(crate::ParseError::InvalidUrl(e.clone()));
-                        }
-                        url.unwrap()
-                    };
-                    let t = crate::token_ignore_char!(
-                        iter,
-                        ' ',
-                        return Ok(Reference {
-                            name: name.into_boxed_str(),
-                            title: String::new().into_boxed_str(),
-                            href: url,
-                        })
-                    );
-                    let (t, n) = crate::token_ignore_char!(iter, '"', 1, t);
-                    let t = if n == 0 {
-                        crate::token_ignore_char!(iter, '(', 1, t).0
-                    } else {
-                        t
-                    };
-                    let title = crate::token_combine_except!(
-                        t,
-                        iter,
-                        ParseToken::RepeatSpecial(')', 1),
-                        {
-                            if n == 0 {
-                                return Err(crate::ParseError::UnexpectedChar(')'));
-                            }
-                            break;
-                        },
-                        ParseToken::RepeatSpecial('(', 1),
-                        {
-                            if n == 0 {
-                                return Err(crate::ParseError::UnexpectedChar('('));
-                            }
-                            break;
-                        },
-                        ParseToken::RepeatSpecial('"', 1),
-                        {
-                            if n == 0 {
-                                return Err(crate::ParseError::UnexpectedChar('"'));
-                            }
-                            break;
-                        },
-                        ParseToken::RepeatSpecial('\'', 1),
-                        {
-                            if n == 0 {
-                                return Err(crate::ParseError::UnexpectedChar('\''));
-                            }
-                            break;
-                        }
-                    );
-                    return Ok(Reference {
+                    let tokens = iter.as_slice();
+                    let chars = chars_of(tokens);
+                    let (href, title, end) = link_target(tokens, &chars)?;
+                    if end < chars.len() {
+                        return Err(fail_at(tokens, &chars, end, "end of input"));
+                    }
+                    let href = link_source(href)?;
+                    iter.nth(tokens.len());
+                    Ok(Reference {
                         name: name.into_boxed_str(),
-                        title: title.into_boxed_str(),
-                        href: url,
-                    });
+                        title: title.unwrap_or_default().into_boxed_str(),
+                        href,
+                        span: crate::consumed(before, iter),
+                    })
+                }
+                ParseToken::RepeatSpecial(c, _) => {
+                    Err(crate::ParseErrorKind::UnexpectedChar(*c).into())
+                }
+                ParseToken::String(s) => {
+                    Err(crate::ParseErrorKind::UnexpectedString(s.to_owned()).into())
                 }
-                ParseToken::RepeatSpecial(c, _) => Err(crate::ParseError::UnexpectedChar(*c)),
-                ParseToken::String(s) => Err(crate::ParseError::UnexpectedString(s.to_owned())),
                 ParseToken::Number(_, _) => {
-                    return Err(crate::ParseError::UnexpectedString(t.to_string()))
+                    Err(crate::ParseErrorKind::UnexpectedString(t.to_string()).into())
                 }
             }
         } else {
-            Err(crate::ParseError::EmptyDocument)
-        };
+            Err(crate::ParseErrorKind::EmptyDocument.into())
+        }
     }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for LinkSource {
     fn to_string(&self) -> String {
         match self {
             LinkSource::None => String::new(),
             LinkSource::Ref(r) => r.to_string(),
             LinkSource::Url(u) => u.to_string(),
+            LinkSource::Relative(p) => p.to_string(),
         }
     }
 }
 
+#[allow(clippy::to_string_trait_impl)]
 impl ToString for Reference {
     fn to_string(&self) -> String {
         let mut s = format!("[{}]: <{}>", self.name, self.href.to_string());
         if !self.title.is_empty() {
-            s.push_str(&format!("({})", self.title))
+            s.push_str(&format!(" \"{}\"", self.title))
         }
-        return s;
+        s
     }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ReferenceBuilder {
     name: String,
-    href: Option<url::Url>,
+    href: Option<LinkSource>,
     title: String,
 }
 
@@ -605,7 +2308,11 @@ impl ReferenceBuilder {
         self
     }
     pub fn href(mut self, u: url::Url) -> Self {
-        self.href = Some(u);
+        self.href = Some(LinkSource::Url(u));
+        self
+    }
+    pub fn relative(mut self, path: RelativePath) -> Self {
+        self.href = Some(LinkSource::Relative(path));
         self
     }
 }
@@ -625,36 +2332,88 @@ impl crate::Builder for ReferenceBuilder {
             name: self.name.into_boxed_str(),
             title: self.title.into_boxed_str(),
             href: url,
+            span: Span::default(),
         })
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ItemBuilder {
-    Bold(String),
-    BoldItalic(String),
+    Bold(Vec<Item>),
+    BoldItalic(Vec<Item>),
     Def(String),
-    Italic(String),
+    Italic(Vec<Item>),
+    Strikethrough(Vec<Item>),
+    Highlight(Vec<Item>),
+    Superscript(Vec<Item>),
+    Subscript(Vec<Item>),
     Link(Link),
+    Code(String),
+    Break,
+    HardBreak(HardBreak),
+    Html(String),
+    #[default]
     Undefined,
 }
 
 impl ItemBuilder {
+    /// Toggles strong emphasis. Text, code, links and breaks are wrapped in
+    /// it, and bold content holding a single item is unwrapped again.
     pub fn bold(self) -> Self {
         match self {
-            Self::Bold(s) => Self::Def(s),
-            Self::BoldItalic(s) => Self::Italic(s),
-            Self::Italic(s) => Self::BoldItalic(s),
-            Self::Def(s) => Self::Bold(s),
-            _ => Self::Undefined,
+            Self::Bold(c) => Self::unwrap(c),
+            Self::BoldItalic(c) => Self::Italic(c),
+            Self::Italic(c) => Self::BoldItalic(c),
+            Self::Undefined => Self::Undefined,
+            item => item.wrap().map_or(Self::Undefined, Self::Bold),
         }
     }
+    /// Toggles emphasis, the same way as [`ItemBuilder::bold`].
     pub fn italic(self) -> Self {
         match self {
-            Self::Bold(s) => Self::BoldItalic(s),
-            Self::BoldItalic(s) => Self::Bold(s),
-            Self::Italic(s) => Self::Def(s),
-            Self::Def(s) => Self::Italic(s),
+            Self::Bold(c) => Self::BoldItalic(c),
+            Self::BoldItalic(c) => Self::Bold(c),
+            Self::Italic(c) => Self::unwrap(c),
+            Self::Undefined => Self::Undefined,
+            item => item.wrap().map_or(Self::Undefined, Self::Italic),
+        }
+    }
+    /// Toggles strikethrough, the same way as [`ItemBuilder::bold`].
+    pub fn strikethrough(self) -> Self {
+        match self {
+            Self::Strikethrough(c) => Self::unwrap(c),
+            Self::Undefined => Self::Undefined,
+            item => item.wrap().map_or(Self::Undefined, Self::Strikethrough),
+        }
+    }
+    /// Toggles highlighting, the same way as [`ItemBuilder::bold`].
+    pub fn highlight(self) -> Self {
+        match self {
+            Self::Highlight(c) => Self::unwrap(c),
+            Self::Undefined => Self::Undefined,
+            item => item.wrap().map_or(Self::Undefined, Self::Highlight),
+        }
+    }
+    /// Toggles superscript, the same way as [`ItemBuilder::bold`].
+    pub fn superscript(self) -> Self {
+        match self {
+            Self::Superscript(c) => Self::unwrap(c),
+            Self::Undefined => Self::Undefined,
+            item => item.wrap().map_or(Self::Undefined, Self::Superscript),
+        }
+    }
+    /// Toggles subscript, the same way as [`ItemBuilder::bold`].
+    pub fn subscript(self) -> Self {
+        match self {
+            Self::Subscript(c) => Self::unwrap(c),
+            Self::Undefined => Self::Undefined,
+            item => item.wrap().map_or(Self::Undefined, Self::Subscript),
+        }
+    }
+    pub fn code(self) -> Self {
+        match self {
+            Self::Code(s) => Self::Def(s),
+            Self::Def(s) => Self::Code(s),
             _ => Self::Undefined,
         }
     }
@@ -662,71 +2421,187 @@ impl ItemBuilder {
         Self::Link(l)
     }
     pub fn content(self, s: String) -> Self {
+        let text = || vec![Item::Def(s.clone().into_boxed_str(), Span::default())];
         match self {
-            Self::Bold(_) => Self::Bold(s),
-            Self::BoldItalic(_) => Self::BoldItalic(s),
-            Self::Italic(_) => Self::Italic(s),
+            Self::Bold(_) => Self::Bold(text()),
+            Self::BoldItalic(_) => Self::BoldItalic(text()),
+            Self::Italic(_) => Self::Italic(text()),
+            Self::Strikethrough(_) => Self::Strikethrough(text()),
+            Self::Highlight(_) => Self::Highlight(text()),
+            Self::Superscript(_) => Self::Superscript(text()),
+            Self::Subscript(_) => Self::Subscript(text()),
+            Self::Code(_) => Self::Code(s),
             _ => Self::Def(s),
         }
     }
+    /// Adds an item to the content of formatted text, such as bold or
+    /// italic text.
+    pub fn push(mut self, item: Item) -> Self {
+        match &mut self {
+            Self::Bold(c)
+            | Self::BoldItalic(c)
+            | Self::Italic(c)
+            | Self::Strikethrough(c)
+            | Self::Highlight(c)
+            | Self::Superscript(c)
+            | Self::Subscript(c) => {
+                c.push(item);
+                self
+            }
+            _ => Self::Undefined,
+        }
+    }
+    fn wrap(self) -> Option<Vec<Item>> {
+        crate::Builder::build(self).ok().map(|i| vec![i])
+    }
+    fn unwrap(c: Vec<Item>) -> Self {
+        match <[Item; 1]>::try_from(c) {
+            Ok([item]) => item.into(),
+            Err(_) => Self::Undefined,
+        }
+    }
+}
+
+impl From<Item> for ItemBuilder {
+    fn from(item: Item) -> Self {
+        match item {
+            Item::Bold(c, _) => Self::Bold(c.into_vec()),
+            Item::BoldItalic(c, _) => Self::BoldItalic(c.into_vec()),
+            Item::Def(s, _) => Self::Def(s.into_string()),
+            Item::Italic(c, _) => Self::Italic(c.into_vec()),
+            Item::Strikethrough(c, _) => Self::Strikethrough(c.into_vec()),
+            Item::Highlight(c, _) => Self::Highlight(c.into_vec()),
+            Item::Superscript(c, _) => Self::Superscript(c.into_vec()),
+            Item::Subscript(c, _) => Self::Subscript(c.into_vec()),
+            Item::Link(l) => Self::Link(l),
+            Item::Code(s, _) => Self::Code(s.into_string()),
+            Item::Break(_) => Self::Break,
+            Item::HardBreak(b, _) => Self::HardBreak(b),
+            Item::Html(s, _) => Self::Html(s.into_string()),
+        }
+    }
 }
 
 impl crate::Builder for ItemBuilder {
     type Output = Item;
     fn build(self) -> Result<Self::Output, crate::Error> {
         match self {
-            Self::Bold(s) => Ok(Self::Output::Bold(s.into_boxed_str())),
-            Self::BoldItalic(s) => Ok(Self::Output::BoldItalic(s.into_boxed_str())),
-            Self::Def(s) => Ok(Self::Output::Def(s.into_boxed_str())),
-            Self::Italic(s) => Ok(Self::Output::Italic(s.into_boxed_str())),
+            Self::Bold(c) => Ok(Self::Output::Bold(c.into_boxed_slice(), Span::default())),
+            Self::BoldItalic(c) => Ok(Self::Output::BoldItalic(
+                c.into_boxed_slice(),
+                Span::default(),
+            )),
+            Self::Def(s) => Ok(Self::Output::Def(s.into_boxed_str(), Span::default())),
+            Self::Italic(c) => Ok(Self::Output::Italic(c.into_boxed_slice(), Span::default())),
+            Self::Strikethrough(c) => Ok(Self::Output::Strikethrough(
+                c.into_boxed_slice(),
+                Span::default(),
+            )),
+            Self::Highlight(c) => Ok(Self::Output::Highlight(
+                c.into_boxed_slice(),
+                Span::default(),
+            )),
+            Self::Superscript(c) => Ok(Self::Output::Superscript(
+                c.into_boxed_slice(),
+                Span::default(),
+            )),
+            Self::Subscript(c) => Ok(Self::Output::Subscript(
+                c.into_boxed_slice(),
+                Span::default(),
+            )),
             Self::Link(l) => Ok(Self::Output::Link(l)),
+            Self::Code(s) => Ok(Self::Output::Code(s.into_boxed_str(), Span::default())),
+            Self::Break => Ok(Self::Output::Break(Span::default())),
+            Self::HardBreak(b) => Ok(Self::Output::HardBreak(b, Span::default())),
+            Self::Html(s) => match crate::html::inline_len(&s) {
+                Some(n) if n == s.len() => {
+                    Ok(Self::Output::Html(s.into_boxed_str(), Span::default()))
+                }
+                _ => Err(crate::Error::InvalidData),
+            },
             Self::Undefined => Err(crate::Error::IncompleteData),
         }
     }
 }
 
-impl Default for ItemBuilder {
-    fn default() -> Self {
-        ItemBuilder::Undefined
-    }
-}
-
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct LinkBuilder {
-    name: String,
+    items: Vec<Item>,
     src: LinkSource,
+    title: Option<Box<str>>,
     img: bool,
+    auto: bool,
+    bare: bool,
+    style: RefStyle,
 }
 
 impl LinkBuilder {
+    /// Sets the link text to plain `name`.
     pub fn name(mut self, name: String) -> Self {
-        self.name = name;
+        self.items = vec![Item::Def(name.into_boxed_str(), Span::default())];
         self
     }
     pub fn name_push(&mut self, c: char) {
-        self.name.push(c);
+        self.name_push_str(c.encode_utf8(&mut [0; 4]));
     }
     pub fn name_push_str(&mut self, s: &str) {
-        self.name.push_str(s);
+        if let Some(Item::Def(text, _)) = self.items.last_mut() {
+            *text = format!("{}{}", text, s).into_boxed_str();
+        } else {
+            self.items
+                .push(Item::Def(s.to_string().into_boxed_str(), Span::default()));
+        }
+    }
+    /// Adds an inline item, such as emphasis or code, to the link text.
+    pub fn push(mut self, item: Item) -> Self {
+        self.items.push(item);
+        self
     }
     pub fn href(mut self, href: url::Url) -> Self {
         self.src = LinkSource::Url(href);
         self
     }
+    pub fn relative(mut self, path: RelativePath) -> Self {
+        self.src = LinkSource::Relative(path);
+        self
+    }
     pub fn reference(mut self, r: Reference) -> Self {
         self.src = LinkSource::Ref(r.name.clone());
         self
     }
+    /// Sets how the link to a reference is written. Collapsed and shortcut
+    /// references are written in full unless the link text matches the label.
+    pub fn style(mut self, style: RefStyle) -> Self {
+        self.style = style;
+        self
+    }
+    pub fn title(mut self, s: &str) -> Self {
+        self.title = Some(s.into());
+        self
+    }
     pub fn make_img(mut self) -> Self {
         self.img = true;
         self
     }
+    /// Writes the link as its own destination, `<https://example.com>`,
+    /// where its text allows it.
+    pub fn make_autolink(mut self) -> Self {
+        self.auto = true;
+        self
+    }
+    /// Writes the link as its own destination without `<` and `>`, as in
+    /// `https://example.com`, where its text allows it.
+    pub fn make_bare(mut self) -> Self {
+        self.auto = true;
+        self.bare = true;
+        self
+    }
 }
 
 impl crate::Builder for LinkBuilder {
     type Output = Link;
     fn build(self) -> Result<Self::Output, crate::Error> {
-        if self.name.is_empty() {
+        if self.items.iter().all(|i| i.text().is_empty()) {
             return Err(crate::Error::IncompleteData);
         }
         if let LinkSource::Ref(s) = &self.src {
@@ -735,34 +2610,33 @@ impl crate::Builder for LinkBuilder {
             }
         };
         Ok(Self::Output {
-            name: self.name.into_boxed_str(),
+            items: self.items.into_boxed_slice(),
             src: self.src,
+            title: self.title,
             img: self.img,
+            auto: self.auto,
+            bare: self.bare,
+            style: self.style,
+            span: Span::default(),
         })
     }
 }
 
-impl Default for LinkSource {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
-impl Default for HeadingLvl {
-    fn default() -> Self {
-        Self::Level1
-    }
-}
-
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct HeadingBuilder {
-    content: String,
+    items: Vec<Item>,
     level: HeadingLvl,
 }
 
 impl HeadingBuilder {
+    /// Adds plain text to the heading.
     pub fn content(mut self, s: String) -> Self {
-        self.content = s;
+        self.items
+            .push(Item::Def(s.into_boxed_str(), Span::default()));
+        self
+    }
+    pub fn push(mut self, item: Item) -> Self {
+        self.items.push(item);
         self
     }
     pub fn level(mut self, l: HeadingLvl) -> Self {
@@ -774,16 +2648,51 @@ impl HeadingBuilder {
 impl crate::Builder for HeadingBuilder {
     type Output = Heading;
     fn build(self) -> Result<Self::Output, crate::Error> {
-        if self.content.is_empty() {
+        if self.items.is_empty() {
             return Err(crate::Error::IncompleteData);
         }
         Ok(Self::Output {
             level: self.level,
-            content: self.content,
+            items: self.items.into_boxed_slice(),
+            extensions: Extensions::default(),
+            span: Span::default(),
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ParagraphBuilder {
+    items: Vec<Item>,
+}
+
+impl ParagraphBuilder {
+    /// Adds plain text to the paragraph.
+    pub fn content(mut self, s: String) -> Self {
+        self.items
+            .push(Item::Def(s.into_boxed_str(), Span::default()));
+        self
+    }
+    pub fn push(mut self, item: Item) -> Self {
+        self.items.push(item);
+        self
+    }
+}
+
+impl crate::Builder for ParagraphBuilder {
+    type Output = Paragraph;
+    fn build(self) -> Result<Self::Output, crate::Error> {
+        if self.items.is_empty() {
+            return Err(crate::Error::IncompleteData);
+        }
+        Ok(Self::Output {
+            items: self.items.into_boxed_slice(),
+            extensions: Extensions::default(),
+            span: Span::default(),
         })
     }
 }
 
+crate::impl_from_str!(Paragraph);
 crate::impl_from_str!(Heading);
 crate::impl_from_str!(Item);
 crate::impl_from_str!(Reference);